@@ -0,0 +1,39 @@
+use std::time::Instant;
+
+/// Why a batch was flushed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FlushReason {
+    /// The batch reached `max_len`.
+    MaxLen,
+    /// The batch reached `max_weight`, or the next element would have exceeded it.
+    MaxWeight,
+    /// The buffer reached its `capacity`.
+    Capacity,
+    /// The `interval` elapsed.
+    Clock,
+    /// `trigger` was called.
+    Manual,
+    /// The trigger was dropped.
+    Drop,
+    /// The trigger was shut down.
+    Shutdown,
+    /// The elements a previous process left in the write-ahead log.
+    Replay,
+}
+
+/// Metadata of a flushed batch, handed to the consumers set with `*_with_info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchInfo {
+    /// Why the batch was flushed.
+    pub reason: FlushReason,
+    /// Number of elements pushed into the batch.
+    pub len: usize,
+    /// Sequence number of the batch, starting from 0 for each trigger.
+    pub batch_id: u64,
+    /// When the first element of the batch was pushed.
+    pub first_push_at: Instant,
+    /// When the batch was taken out of the buffer.
+    pub flushed_at: Instant,
+    /// The `name` of the trigger.
+    pub trigger_name: String,
+}
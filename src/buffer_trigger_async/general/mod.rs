@@ -1,60 +1,149 @@
-use std::{fmt, time::Duration};
+use crate::error::panic_message;
+use crate::timer::{Target, Timer};
+use crate::timer_mode::next_tick;
+use crate::{
+    BatchInfo, Clock, ConsumerError, DeadLetter, Error, FlushReason, OverflowPolicy, PushError,
+    RetryPolicy, ShutdownReport, Storage, TimerMode, Wal,
+};
+use lifetime_thread::Inner;
+use std::{
+    any::Any,
+    fmt,
+    future::Future,
+    io, mem,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 use tokio::{
+    runtime::Handle,
     sync::{
         mpsc::{Receiver, Sender},
-        Mutex, RwLock,
+        Mutex, Notify, RwLock, Semaphore,
     },
-    time::sleep,
+    task::{self, JoinHandle},
+    time::timeout,
 };
 
 pub mod builder;
-struct Locker<E, C, P>
-where
-    P: fmt::Debug,
-    E: fmt::Debug,
-    C: fmt::Debug,
-{
-    payload: Option<P>,
-    /// Whether the timed task has been set
-    clock: bool,
-    /// Number of container elements
-    get_len: fn(&Option<P>) -> usize,
 
-    incr_len: fn(&mut Option<P>),
+/// The future returned by a consumer, awaited by `trigger`.
+///
+/// A failed consumer hands the container back so that it can be retried.
+pub type ConsumerFuture<C> = Pin<Box<dyn Future<Output = Result<(), (C, ConsumerError)>> + Send>>;
+/// The function executed after the trigger condition is met.
+pub type Consumer<C> = Box<dyn Fn(C, &BatchInfo) -> ConsumerFuture<C> + Send + Sync>;
+/// Receives the elements pushed but not buffered.
+pub type OnDrop<E> = Box<dyn Fn(E) + Send + Sync>;
+/// Receives the batch metadata and the message of a panicking consumer.
+pub type OnPanic = Box<dyn Fn(&BatchInfo, &str) + Send + Sync>;
+/// The weight of an element, e.g. its size in bytes.
+pub type Weigher<E> = Box<dyn Fn(&E) -> usize + Send + Sync>;
 
-    clear_len: fn(&mut Option<P>),
+struct Locker<S> {
+    /// The elements of the current batch.
+    storage: S,
+    /// When the current window expires, set by the first element of a batch
+    /// and moved on by each push in `TimerMode::Debounce`
+    deadline: Option<Instant>,
+    /// Elements pushed since the last trigger, their permits are released once consumed.
+    pushed: usize,
+    /// Total weight of the container elements
+    weight: usize,
+    /// When the first element of the container was pushed
+    first_push_at: Option<Instant>,
+    /// Number of batches taken so far, also the generation of the current window
+    batches: u64,
+    clock: Arc<dyn Clock>,
+}
 
-    get_container: fn(&mut Option<P>) -> &mut C,
-    /// accumulator function
-    accumulator: fn(&mut C, E),
-    /// get and clear container
-    get_and_clear_container: fn(&mut Option<P>) -> C,
+impl<S> Locker<S> {
+    /// Swap the container out, with the metadata of the batch.
+    fn take<E>(&mut self, name: &str, reason: FlushReason) -> (S::Batch, BatchInfo)
+    where
+        S: Storage<E>,
+    {
+        self.deadline = None;
+        self.weight = 0;
+        let flushed_at = self.clock.now();
+        let info = BatchInfo {
+            reason,
+            len: mem::take(&mut self.pushed),
+            batch_id: self.batches,
+            first_push_at: self.first_push_at.take().unwrap_or(flushed_at),
+            flushed_at,
+            trigger_name: name.to_owned(),
+        };
+        self.batches += 1;
+        (self.storage.take_batch(), info)
+    }
 }
 
 /// General `BufferTrigger`
 ///
-/// Set your own container to store in the current service
-pub struct General<E, C, P>
+/// Set your own `Storage` to keep the elements in
+pub struct General<E, C, S>
 where
-    P: fmt::Debug + Sync + Send + 'static,
+    S: Storage<E, Batch = C> + Sync + 'static,
     E: fmt::Debug + Sync + Send + 'static,
     C: fmt::Debug + Sync + Send + 'static,
 {
     name: String,
-    locker: RwLock<Locker<E, C, P>>,
+    locker: RwLock<Locker<S>>,
     /// The function executed after the trigger condition is met.
-    consumer: fn(C),
+    consumer: Consumer<C>,
+    /// How failed batches are retried.
+    retry_policy: RetryPolicy,
+    /// Where batches go once they exhausted their retries.
+    dead_letter: Option<Box<dyn DeadLetter<C>>>,
     /// how many elements are exceeded
     max_len: usize,
+    weigher: Option<Weigher<E>>,
+    /// how much weight is exceeded, a batch is flushed before an element would exceed it
+    max_weight: usize,
+    /// How many elements may be buffered or being consumed at once.
+    capacity: usize,
+    /// One permit per free slot.
+    space: Option<Arc<Semaphore>>,
+    /// Elements replayed from the write-ahead log into batch 0, they take no slot.
+    replayed: usize,
+    /// What `push` does when the buffer is at `capacity`.
+    overflow_policy: OverflowPolicy<C, E>,
+    on_drop: Option<OnDrop<E>>,
+    /// Number of elements pushed but not buffered: dropped by the
+    /// `overflow_policy`, pushed after `shutdown`, or lost to the write-ahead log.
+    dropped: AtomicU64,
+    on_panic: Option<OnPanic>,
+    /// Number of batches lost to a panicking consumer.
+    panics: AtomicU64,
     /// The maximum time to wait after an element is saved.
     interval: Option<Duration>,
-    sender: Mutex<Sender<()>>,
-    receiver: Mutex<Receiver<()>>,
+    /// Set by `shutdown` and `close`, elements pushed afterwards are dropped.
+    closed: AtomicBool,
+    /// Carries the generation of the window whose timer fired.
+    sender: Mutex<Sender<u64>>,
+    receiver: Mutex<Receiver<u64>>,
+    /// Notified by `close`, the wakeup is kept until the listener waits for it.
+    closing: Notify,
+    /// Closed once the clock listener has stopped.
+    listener: Option<Mutex<Receiver<()>>>,
+    /// The shared wheel that flushes the windows instead of a listener.
+    timer: Option<Timer>,
+    /// How `interval` is measured.
+    timer_mode: TimerMode,
+    /// The source of time.
+    clock: Arc<dyn Clock>,
+    /// Where elements are logged before `push` returns.
+    wal: Option<Arc<Wal<E>>>,
 }
 
-impl<E, C, P> fmt::Debug for General<E, C, P>
+impl<E, C, S> fmt::Debug for General<E, C, S>
 where
-    P: fmt::Debug + Sync + Send,
+    S: Storage<E, Batch = C> + Sync,
     E: fmt::Debug + Sync + Send,
     C: fmt::Debug + Sync + Send,
 {
@@ -63,67 +152,680 @@ where
     }
 }
 
-impl<E, C, P> General<E, C, P>
+impl<E, C, S> General<E, C, S>
 where
-    P: fmt::Debug + Sync + Send,
+    S: Storage<E, Batch = C> + Sync,
     E: fmt::Debug + Sync + Send,
     C: fmt::Debug + Sync + Send,
 {
     pub async fn len(&self) -> usize {
         let c = self.locker.read().await;
-        (c.get_len)(&c.payload)
+        c.storage.len()
     }
+    /// add elements, the `overflow_policy` decides what happens when the buffer is at `capacity`
+    ///
+    /// Elements pushed after `shutdown` are dropped, `try_push` hands them back.
     pub async fn push(&self, value: E) {
-        {
-            let mut c = self.locker.write().await;
-            (c.incr_len)(&mut c.payload);
-            (c.accumulator)((c.get_container)(&mut c.payload), value);
-            if let (false, Some(dur)) = (c.clock, self.interval) {
-                c.clock = true;
-                let sender = self.sender.lock().await.clone();
-                let _ = tokio::spawn(async move {
-                    sleep(dur).await;
-                    sender.send(()).await
-                });
+        if let Some(space) = &self.space {
+            match (self.overflow_policy, space.try_acquire()) {
+                (_, Ok(permit)) => permit.forget(),
+                (OverflowPolicy::Block, Err(_)) => {
+                    if let Ok(permit) = space.acquire().await {
+                        permit.forget();
+                    }
+                }
+                // `try_push` hands a rejected element back, `push` cannot
+                (OverflowPolicy::Reject | OverflowPolicy::DropNewest, Err(_)) => {
+                    return self.drop_element(value)
+                }
+                (OverflowPolicy::DropOldest(evict, peek), Err(_)) => {
+                    if let Err(e) = self.replace(evict, peek, value).await {
+                        self.push_failed(e);
+                    }
+                    return;
+                }
             }
         }
-        if self.len().await >= self.max_len {
-            self.trigger().await
+        if let Err(e) = self.accumulate(value).await {
+            self.push_failed(e);
+        }
+    }
+
+    /// add elements, hands the element back if the buffer is at `capacity` or closed
+    ///
+    /// # Errors
+    ///
+    /// `PushError::Full` if the buffer is at `capacity` with `Block` or `Reject`,
+    /// `PushError::Closed` once the trigger has been shut down,
+    /// `PushError::Wal` if the element could not be appended to the write-ahead log.
+    pub async fn try_push(&self, value: E) -> Result<(), PushError<E>> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(PushError::Closed(value));
+        }
+        if let Some(space) = &self.space {
+            match (self.overflow_policy, space.try_acquire()) {
+                (_, Ok(permit)) => permit.forget(),
+                (OverflowPolicy::Block | OverflowPolicy::Reject, Err(_)) => {
+                    return Err(PushError::Full(value))
+                }
+                (OverflowPolicy::DropNewest, Err(_)) => {
+                    self.drop_element(value);
+                    return Ok(());
+                }
+                (OverflowPolicy::DropOldest(evict, peek), Err(_)) => {
+                    return self.replace(evict, peek, value).await
+                }
+            }
         }
+        self.accumulate(value).await
     }
 
+    /// Add `value` to the container, its permit is released if it is handed back.
+    async fn accumulate(&self, value: E) -> Result<(), PushError<E>> {
+        let weight = self.weigh(&value);
+        let mut guard = self.locker.write().await;
+        if self.closed.load(Ordering::Acquire) {
+            drop(guard);
+            return Err(self.hand_back(PushError::Closed(value)));
+        }
+        let c = &mut *guard;
+        // flush first rather than let the batch exceed `max_weight`
+        let overweight = (c.weight > 0 && c.weight.saturating_add(weight) > self.max_weight)
+            .then(|| c.take(&self.name, FlushReason::MaxWeight));
+        if let Err(e) = self.log(c.batches, &value).await {
+            log::error!("{self:?} write-ahead log failed: {e}");
+            drop(guard);
+            if let Some(batch) = overweight {
+                let _ = self.consume_batch(batch).await;
+            }
+            return Err(self.hand_back(PushError::Wal(value)));
+        }
+        let now = self.clock.now();
+        let first_push_at = *c.first_push_at.get_or_insert(now);
+        c.storage.push(value);
+        c.pushed += 1;
+        c.weight = c.weight.saturating_add(weight);
+        // the timer carries the generation of the window it was armed for
+        let mut arm = None;
+        let deadline = self
+            .interval
+            .and_then(|dur| self.timer_mode.deadline(first_push_at, now, dur));
+        if let Some(deadline) = deadline {
+            if c.deadline.is_none() {
+                arm = Some((deadline, c.batches));
+            }
+            c.deadline = Some(deadline);
+        }
+        // a full buffer is flushed, otherwise pushes would wait forever
+        let full = if c.storage.len() >= self.max_len {
+            Some(FlushReason::MaxLen)
+        } else if c.weight >= self.max_weight {
+            Some(FlushReason::MaxWeight)
+        } else if c.pushed >= self.capacity {
+            Some(FlushReason::Capacity)
+        } else {
+            None
+        };
+        drop(guard);
+        if let Some((deadline, window)) = arm {
+            self.arm(deadline, window).await;
+        }
+        if let Some(batch) = overweight {
+            let _ = self.consume_batch(batch).await;
+        }
+        if let Some(reason) = full {
+            let _ = self.flush(reason).await;
+        }
+        Ok(())
+    }
+
+    fn weigh(&self, value: &E) -> usize {
+        self.weigher.as_ref().map_or(0, |weigher| weigher(value))
+    }
+
+    /// Take the container out and await the consumer.
+    ///
+    /// The lock is released before the consumer runs, so the returned
+    /// future completes only after the batch has been consumed.
     pub async fn trigger(&self) {
-        if !self.is_empty().await {
-            let mut c = self.locker.write().await;
-            c.clock = false;
-            (c.clear_len)(&mut c.payload);
-            (self.consumer)((c.get_and_clear_container)(&mut c.payload));
+        let _ = self.flush(FlushReason::Manual).await;
+    }
+
+    /// Manual trigger, reports why the batch was not consumed
+    ///
+    /// # Errors
+    ///
+    /// `Error::ConsumerFailed` if the consumer gave up on the batch.
+    pub async fn try_trigger(&self) -> Result<(), Error> {
+        self.flush(FlushReason::Manual).await
+    }
+
+    async fn flush(&self, reason: FlushReason) -> Result<(), Error> {
+        let mut c = self.locker.write().await;
+        if c.storage.len() == 0 {
+            return Ok(());
+        }
+        let batch = c.take(&self.name, reason);
+        drop(c);
+        self.consume_batch(batch).await
+    }
+
+    /// Flush the window `window` once its deadline has passed, timers of older
+    /// windows are ignored. A `Ticker` on a wheel flushes and schedules its next tick instead.
+    async fn expire(&self, window: u64) -> Result<(), Error> {
+        if let (Some(heartbeat), Some(dur), Some(timer)) =
+            (self.timer_mode.ticker(), self.interval, &self.timer)
+        {
+            timer.schedule(next_tick(&*self.clock, dur), window);
+            return self.tick(heartbeat).await;
+        }
+        let mut c = self.locker.write().await;
+        if c.batches != window || c.storage.len() == 0 {
+            return Ok(());
+        }
+        match c.deadline {
+            Some(d) if d <= self.clock.now() => {}
+            Some(d) => {
+                // pushes moved a debounced deadline on
+                drop(c);
+                self.arm(d, window).await;
+                return Ok(());
+            }
+            None => return Ok(()),
+        }
+        let batch = c.take(&self.name, FlushReason::Clock);
+        drop(c);
+        self.consume_batch(batch).await
+    }
+
+    /// Flush on a tick of a `Ticker`, an empty batch too with `heartbeat`.
+    async fn tick(&self, heartbeat: bool) -> Result<(), Error> {
+        let mut c = self.locker.write().await;
+        if !heartbeat && c.storage.len() == 0 {
+            return Ok(());
+        }
+        let batch = c.take(&self.name, FlushReason::Clock);
+        drop(c);
+        self.consume_batch(batch).await
+    }
+
+    /// Expire the window `window` at `deadline`.
+    async fn arm(&self, deadline: Instant, window: u64) {
+        if let Some(timer) = &self.timer {
+            timer.schedule(deadline, window);
+            return;
+        }
+        let sender = self.sender.lock().await.clone();
+        let (clock, wait) = (
+            self.clock.clone(),
+            deadline.saturating_duration_since(self.clock.now()),
+        );
+        drop(tokio::spawn(async move {
+            clock.async_sleep(wait).await;
+            sender.send(window).await
+        }));
+    }
+
+    /// Slots taken by the elements of a batch.
+    const fn slots(&self, info: &BatchInfo) -> usize {
+        if info.batch_id == 0 {
+            info.len.saturating_sub(self.replayed)
+        } else {
+            info.len
+        }
+    }
+
+    /// Consume a batch taken from the locker and release its permits.
+    ///
+    /// If the returned future is dropped, the attempt running is left to complete.
+    async fn consume_batch(&self, batch: (C, BatchInfo)) -> Result<(), Error> {
+        self.settle(batch, false).await
+    }
+
+    /// Consume a batch taken from the locker and release its permits, `abort`
+    /// cancels the attempt running if the returned future is dropped.
+    async fn settle(&self, (container, info): (C, BatchInfo), abort: bool) -> Result<(), Error> {
+        let mut settle = Settle {
+            space: self.space.clone(),
+            wal: self.wal.clone(),
+            len: self.slots(&info),
+            batch_id: info.batch_id,
+            running: None,
+            abort,
+        };
+        let consumed = self.consume(container, &info, &mut settle.running).await;
+        // a dead-lettered batch must not be replayed either
+        settle
+            .finish(
+                consumed.is_ok()
+                    || (self.dead_letter.is_some()
+                        && matches!(consumed, Err(Error::ConsumerFailed { .. }))),
+            )
+            .await;
+        consumed
+    }
+
+    /// Evict a buffered element to make room for `value`, without taking a permit.
+    ///
+    /// `value` is dropped if nothing can be evicted, and handed back if the
+    /// eviction cannot be logged, the batch is then left as it was.
+    async fn replace(
+        &self,
+        evict: fn(&mut C) -> Option<E>,
+        peek: fn(&C) -> Option<&E>,
+        value: E,
+    ) -> Result<(), PushError<E>> {
+        let mut guard = self.locker.write().await;
+        if self.closed.load(Ordering::Acquire) {
+            return Err(PushError::Closed(value));
+        }
+        let c = &mut *guard;
+        // log the eviction first, so that a failure leaves the batch untouched
+        let logged = self
+            .log_eviction(c.batches, c.storage.peek(peek), &value)
+            .await;
+        let dropped = match logged {
+            Ok(true) => match c.storage.evict(evict) {
+                Some(evicted) => {
+                    c.weight = (c.weight + self.weigh(&value)).saturating_sub(self.weigh(&evicted));
+                    c.storage.push(value);
+                    evicted
+                }
+                None => value,
+            },
+            Ok(false) => value,
+            Err(e) => {
+                log::error!("{self:?} write-ahead log failed: {e}");
+                return Err(PushError::Wal(value));
+            }
+        };
+        drop(guard);
+        self.drop_element(dropped);
+        Ok(())
+    }
+
+    /// Release the permit of an element that is handed back.
+    fn hand_back(&self, e: PushError<E>) -> PushError<E> {
+        if let Some(space) = &self.space {
+            space.add_permits(1);
+        }
+        e
+    }
+
+    /// Append `value` to the write-ahead log, if there is one.
+    async fn log(&self, batch: u64, value: &E) -> io::Result<()> {
+        match &self.wal {
+            Some(wal) => write(wal, batch, wal.pushed(value)?).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Log that the `peeked` element makes room for `value`, if there is a
+    /// write-ahead log. False if it must not be evicted, as it cannot be logged.
+    async fn log_eviction(&self, batch: u64, peeked: Option<&E>, value: &E) -> io::Result<bool> {
+        match (&self.wal, peeked) {
+            (Some(wal), Some(evicted)) => {
+                let lines = wal.replaced(evicted, value)?;
+                write(wal, batch, lines).await.map(|()| true)
+            }
+            (Some(_), None) => Ok(false),
+            (None, _) => Ok(true),
+        }
+    }
+
+    /// Drop the element `push` could not buffer, `try_push` would hand it back.
+    fn push_failed(&self, e: PushError<E>) {
+        log::warn!("{self:?} push dropped an element: {e}");
+        self.drop_element(e.into_inner());
+    }
+
+    fn drop_element(&self, value: E) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        if let Some(on_drop) = &self.on_drop {
+            on_drop(value);
+        }
+    }
+
+    /// Number of elements pushed but not buffered: dropped by the
+    /// `overflow_policy`, pushed after `shutdown`, or lost to the write-ahead log.
+    #[must_use]
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Run the consumer, retrying with backoff until it succeeds or gives up.
+    ///
+    /// The attempt being awaited is kept in `running`.
+    async fn consume(
+        &self,
+        mut container: C,
+        info: &BatchInfo,
+        running: &mut Option<Attempt<C>>,
+    ) -> Result<(), Error> {
+        let mut attempt = 1;
+        loop {
+            // the consumer runs in its own task so that a panic surfaces as a `JoinError`
+            let fut =
+                match panic::catch_unwind(AssertUnwindSafe(|| (self.consumer)(container, info))) {
+                    Ok(fut) => fut,
+                    Err(payload) => return Err(self.panicked(&*payload, info)),
+                };
+            let joined = running.insert(tokio::spawn(fut)).await;
+            *running = None;
+            let (c, e) = match joined {
+                Ok(Ok(())) => return Ok(()),
+                Ok(Err(failed)) => failed,
+                Err(e) if e.is_panic() => return Err(self.panicked(&*e.into_panic(), info)),
+                Err(e) => {
+                    log::error!(
+                        "{self:?} consumer cancelled on batch {}: {e}",
+                        info.batch_id
+                    );
+                    return Err(Error::Closed);
+                }
+            };
+            if let Some(backoff) = self.retry_policy.next_backoff(attempt) {
+                log::warn!(
+                    "{self:?} consumer failed on attempt {attempt}, retry in {backoff:?}: {e}"
+                );
+                self.clock.async_sleep(backoff).await;
+                attempt += 1;
+                container = c;
+            } else {
+                self.retry_policy.give_up(&e, attempt);
+                let reason = e.to_string();
+                if let Some(dead_letter) = &self.dead_letter {
+                    dead_letter.dead_letter(c, e, attempt);
+                }
+                return Err(Error::ConsumerFailed {
+                    attempts: attempt,
+                    reason,
+                });
+            }
         }
     }
 
+    /// Count and report a panicking consumer, its batch is lost.
+    fn panicked(&self, payload: &(dyn Any + Send), info: &BatchInfo) -> Error {
+        let reason = panic_message(payload);
+        log::error!(
+            "{self:?} consumer panicked on batch {}: {reason}",
+            info.batch_id
+        );
+        self.panics.fetch_add(1, Ordering::Relaxed);
+        if let Some(on_panic) = &self.on_panic {
+            on_panic(info, &reason);
+        }
+        Error::ConsumerPanicked { reason }
+    }
+
+    /// Number of batches lost to a panicking consumer.
+    #[must_use]
+    pub fn panics(&self) -> u64 {
+        self.panics.load(Ordering::Relaxed)
+    }
+
     pub async fn is_empty(&self) -> bool {
         self.len().await == 0
     }
 
-    /// start clock trigger listener
+    /// The total weight of the elements, 0 without a `weigher`
+    pub async fn weight(&self) -> usize {
+        self.locker.read().await.weight
+    }
+
+    /// Stop accepting elements, flush the buffered ones and wait for the clock
+    /// listener to stop, for at most `timeout` altogether.
+    ///
+    /// Elements pushed afterwards are dropped. A consumer still running at the
+    /// deadline is cancelled, its batch is reported as left like a failed one.
+    pub async fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        let start = self.clock.now();
+        let mut guard = self.locker.write().await;
+        self.closed.store(true, Ordering::Release);
+        let c = &mut *guard;
+        let batch = (c.storage.len() > 0).then(|| c.take(&self.name, FlushReason::Shutdown));
+        drop(guard);
+        let mut report = ShutdownReport::default();
+        if let Some(batch) = batch {
+            let len = batch.1.len;
+            match tokio::time::timeout(timeout, self.settle(batch, true)).await {
+                Ok(Ok(())) => report.flushed = len,
+                Ok(Err(_)) | Err(_) => report.left = len,
+            }
+        }
+        self.close();
+        report.listener_stopped = self
+            .join_listener(
+                timeout.saturating_sub(self.clock.now().saturating_duration_since(start)),
+            )
+            .await;
+        report.left += self.len().await;
+        report
+    }
+
+    /// Wait at most `timeout` for the clock listener to stop.
+    async fn join_listener(&self, timeout: Duration) -> bool {
+        match &self.listener {
+            Some(listener) => {
+                let mut listener = listener.lock().await;
+                matches!(
+                    tokio::time::timeout(timeout, listener.recv()).await,
+                    Ok(None)
+                )
+            }
+            None => true,
+        }
+    }
+
+    /// Stop accepting elements and stop the clock listener without waiting,
+    /// the buffered elements are flushed by the listener as it exits, or when
+    /// the trigger is dropped.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        if let Some(timer) = &self.timer {
+            timer.cancel();
+        }
+        // wake the listener so that it sees `closed`
+        self.closing.notify_one();
+    }
+
+    /// start clock trigger listener, it returns once the trigger is shut down
     pub async fn listen_clock_trigger(&self) {
-        log::info!("{:?} listen_clock_trigger", self);
-        while self.receiver.lock().await.recv().await.is_some() {
-            let clock = self.locker.read().await.clock;
-            if clock {
-                self.trigger().await;
+        log::info!("{self:?} listen_clock_trigger");
+        while self.clock_tick().await {}
+    }
+
+    /// Wait at most an `interval` for the clock, or until the next tick of a
+    /// `Ticker`, false once the trigger is shut down.
+    ///
+    /// The wait is bounded so that the listener notices when the trigger is dropped.
+    async fn clock_tick(&self) -> bool {
+        let interval = self.interval.unwrap_or(Duration::from_secs(1));
+        let now = self.clock.now();
+        let ticker = self
+            .timer_mode
+            .ticker()
+            .map(|heartbeat| (heartbeat, next_tick(&*self.clock, interval)));
+        let wait = ticker.map_or(interval, |(_, at)| at.saturating_duration_since(now));
+        let tick = timeout(wait, async {
+            tokio::select! {
+                window = async { self.receiver.lock().await.recv().await } => window,
+                () = self.closing.notified() => None,
+            }
+        })
+        .await;
+        if self.closed.load(Ordering::Acquire) {
+            return false;
+        }
+        match tick {
+            Ok(Some(window)) => {
+                let _ = self.expire(window).await;
+                true
+            }
+            Ok(None) => false,
+            Err(_) => {
+                if let Some((heartbeat, _)) = ticker {
+                    let _ = self.tick(heartbeat).await;
+                }
+                true
             }
         }
     }
 }
 
-impl<E, C, P> Drop for General<E, C, P>
+/// An async trigger registered with a `TimerWheel`, its windows are flushed on `handle`.
+struct Expiry<E, C, S>
+where
+    S: Storage<E, Batch = C> + Sync + 'static,
+    E: fmt::Debug + Sync + Send + 'static,
+    C: fmt::Debug + Sync + Send + 'static,
+{
+    inner: Arc<Inner<General<E, C, S>>>,
+    handle: Handle,
+}
+
+impl<E, C, S> Target for Expiry<E, C, S>
 where
-    P: fmt::Debug + Sync + Send,
+    S: Storage<E, Batch = C> + Sync,
     E: fmt::Debug + Sync + Send,
     C: fmt::Debug + Sync + Send,
 {
+    fn expire(&self, window: u64) {
+        let expired = super::block_on(async {
+            if let Some(g) = self.inner.get() {
+                let _ = g.expire(window).await;
+            }
+        });
+        if expired.is_none() {
+            log::error!("window {window} lost to a panic");
+        }
+    }
+
+    fn fire(&self, window: u64) {
+        let inner = self.inner.clone();
+        drop(self.handle.spawn(async move {
+            if let Some(g) = inner.get() {
+                let _ = g.expire(window).await;
+            }
+        }));
+    }
+
+    fn is_alive(&self) -> bool {
+        self.inner.get().is_some()
+    }
+}
+
+/// An attempt of the consumer, run in its own task.
+type Attempt<C> = JoinHandle<Result<(), (C, ConsumerError)>>;
+
+/// Settles a batch taken from the locker when dropped: its permits are released
+/// and, once it no longer needs replaying, its write-ahead log segment removed.
+///
+/// It is dropped even if the future flushing the batch is, e.g. a cancelled
+/// `push`, the attempt running then settles the batch once it completes.
+struct Settle<E, C>
+where
+    E: Send + Sync + 'static,
+    C: Send + 'static,
+{
+    space: Option<Arc<Semaphore>>,
+    wal: Option<Arc<Wal<E>>>,
+    len: usize,
+    batch_id: u64,
+    /// The attempt being awaited.
+    running: Option<Attempt<C>>,
+    /// Abort `running` rather than let it complete, at the `shutdown` deadline.
+    abort: bool,
+}
+
+impl<E, C> Settle<E, C>
+where
+    E: Send + Sync + 'static,
+    C: Send + 'static,
+{
+    /// Settle the batch once the consumer is done with it, `consumed` or dead-lettered.
+    async fn finish(mut self, consumed: bool) {
+        release(self.space.take(), self.len);
+        if let (true, Some(wal)) = (consumed, self.wal.take()) {
+            remove(wal, self.batch_id).await;
+        }
+    }
+}
+
+impl<E, C> Drop for Settle<E, C>
+where
+    E: Send + Sync + 'static,
+    C: Send + 'static,
+{
+    fn drop(&mut self) {
+        let (space, wal, len, batch_id) =
+            (self.space.take(), self.wal.take(), self.len, self.batch_id);
+        match (self.running.take(), Handle::try_current()) {
+            (Some(attempt), Ok(handle)) if !self.abort => {
+                drop(handle.spawn(async move {
+                    let consumed = matches!(attempt.await, Ok(Ok(())));
+                    release(space, len);
+                    if let (true, Some(wal)) = (consumed, wal) {
+                        remove(wal, batch_id).await;
+                    }
+                }));
+            }
+            // nothing would settle the batch outside of a runtime
+            (Some(attempt), _) => {
+                attempt.abort();
+                release(space, len);
+            }
+            (None, _) => release(space, len),
+        }
+    }
+}
+
+/// Release the `len` permits of a batch.
+fn release(space: Option<Arc<Semaphore>>, len: usize) {
+    if let Some(space) = space {
+        space.add_permits(len);
+    }
+}
+
+/// Append `lines` to the segment of the batch `batch` on the blocking pool,
+/// the file I/O would block a thread of the runtime.
+async fn write<E>(wal: &Arc<Wal<E>>, batch: u64, lines: Vec<u8>) -> io::Result<()>
+where
+    E: Send + Sync + 'static,
+{
+    let wal = wal.clone();
+    task::spawn_blocking(move || wal.write(batch, &lines))
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e)))
+}
+
+/// Remove the segment of the batch `batch` on the blocking pool.
+async fn remove<E>(wal: Arc<Wal<E>>, batch: u64)
+where
+    E: Send + Sync + 'static,
+{
+    let _ = task::spawn_blocking(move || wal.consumed(batch)).await;
+}
+
+impl<E, C, S> Drop for General<E, C, S>
+where
+    S: Storage<E, Batch = C> + Sync,
+    E: fmt::Debug + Sync + Send,
+    C: fmt::Debug + Sync + Send,
+{
+    /// Flush what is left, prefer `shutdown` to await the consumer.
+    ///
+    /// The batch is retried like any other, on a temporary runtime on a scoped
+    /// thread, because the current runtime may be shutting down.
     fn drop(&mut self) {
-        let _ = self.trigger();
+        let locker = self.locker.get_mut();
+        if locker.storage.len() == 0 {
+            return;
+        }
+        let batch = locker.take(&self.name, FlushReason::Drop);
+        if super::block_on(self.consume_batch(batch)).is_none() {
+            log::error!("{self:?} batch lost on drop");
+        }
     }
 }
@@ -0,0 +1,347 @@
+use crate::storage::Accumulator;
+use lifetime_thread::Outer;
+use std::{collections::HashMap, fmt, future::Future, hash::Hash, mem, pin::Pin, time::Duration};
+use tokio::{
+    runtime::Handle,
+    sync::{Mutex, Notify},
+    time::{timeout, Instant},
+};
+
+/// The function executed with a key and its container after the trigger condition is met.
+pub type KeyedConsumer<K, C> =
+    Box<dyn Fn(K, C) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+/// The buffered elements of one key.
+struct Bucket<C> {
+    container: C,
+    len: usize,
+    /// When the clock flushes this bucket.
+    deadline: Option<Instant>,
+}
+
+struct Partitions<K, E, C>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    E: fmt::Debug + Sync + Send + 'static,
+    C: fmt::Debug + Sync + Send + 'static,
+{
+    name: String,
+    buckets: Mutex<HashMap<K, Bucket<C>>>,
+    /// Notified when a bucket with a deadline is opened.
+    opened: Notify,
+    defalut_container: fn() -> C,
+    accumulator: Accumulator<C, E>,
+    consumer: KeyedConsumer<K, C>,
+    /// how many elements of a key are exceeded
+    max_len: usize,
+    /// The maximum time to wait after an element of a key is saved.
+    interval: Option<Duration>,
+}
+
+impl<K, E, C> fmt::Debug for Partitions<K, E, C>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    E: fmt::Debug + Sync + Send + 'static,
+    C: fmt::Debug + Sync + Send + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "name {}", self.name)
+    }
+}
+
+impl<K, E, C> Partitions<K, E, C>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    E: fmt::Debug + Sync + Send + 'static,
+    C: fmt::Debug + Sync + Send + 'static,
+{
+    async fn push(&self, key: K, value: E) {
+        let mut buckets = self.buckets.lock().await;
+        if let Some(bucket) = buckets.get_mut(&key) {
+            (self.accumulator)(&mut bucket.container, value);
+            bucket.len += 1;
+            if bucket.len < self.max_len {
+                return;
+            }
+        } else {
+            // a new window opens with its first element
+            let mut bucket = Bucket {
+                container: (self.defalut_container)(),
+                len: 1,
+                deadline: self.interval.map(|interval| Instant::now() + interval),
+            };
+            (self.accumulator)(&mut bucket.container, value);
+            if bucket.len < self.max_len {
+                if bucket.deadline.is_some() {
+                    self.opened.notify_one();
+                }
+                buckets.insert(key, bucket);
+            } else {
+                drop(buckets);
+                (self.consumer)(key, bucket.container).await;
+            }
+            return;
+        }
+        let full = buckets.remove_entry(&key);
+        drop(buckets);
+        if let Some((key, bucket)) = full {
+            (self.consumer)(key, bucket.container).await;
+        }
+    }
+
+    async fn trigger(&self) {
+        let buckets = mem::take(&mut *self.buckets.lock().await);
+        for (key, bucket) in buckets {
+            (self.consumer)(key, bucket.container).await;
+        }
+    }
+
+    async fn trigger_key(&self, key: &K) {
+        let bucket = self.buckets.lock().await.remove_entry(key);
+        if let Some((key, bucket)) = bucket {
+            (self.consumer)(key, bucket.container).await;
+        }
+    }
+
+    /// Flush the buckets whose deadline passed, or wait for the next deadline.
+    async fn listen_clock_trigger(&self, interval: Duration) {
+        let mut buckets = self.buckets.lock().await;
+        let now = Instant::now();
+        if !buckets
+            .values()
+            .any(|b| b.deadline.is_some_and(|d| d <= now))
+        {
+            let next = buckets
+                .values()
+                .filter_map(|b| b.deadline)
+                .min()
+                .map_or(interval, |d| d.saturating_duration_since(now));
+            drop(buckets);
+            // a notification while unlocked is kept for `notified`
+            let _ = timeout(next, self.opened.notified()).await;
+            return;
+        }
+        let (expired, rest): (HashMap<_, _>, HashMap<_, _>) = mem::take(&mut *buckets)
+            .into_iter()
+            .partition(|(_, b)| b.deadline.is_some_and(|d| d <= now));
+        *buckets = rest;
+        drop(buckets);
+        for (key, bucket) in expired {
+            (self.consumer)(key, bucket.container).await;
+        }
+    }
+
+    async fn len(&self) -> usize {
+        self.buckets.lock().await.values().map(|b| b.len).sum()
+    }
+}
+
+/// A buffer trigger with an independent buffer per key.
+///
+/// Each key has its own `max_len` and `interval` accounting, and one clock
+/// listener serves all keys. A key holds no state once its buffer is flushed.
+pub struct Keyed<K, E, C>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    E: fmt::Debug + Sync + Send + 'static,
+    C: fmt::Debug + Sync + Send + 'static,
+{
+    partitions: Outer<Partitions<K, E, C>>,
+}
+
+impl<K, E, C> fmt::Debug for Keyed<K, E, C>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    E: fmt::Debug + Sync + Send + 'static,
+    C: fmt::Debug + Sync + Send + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.partitions.fmt(f)
+    }
+}
+
+impl<K, E, C> Keyed<K, E, C>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    E: fmt::Debug + Sync + Send + 'static,
+    C: fmt::Debug + Sync + Send + 'static,
+{
+    /// add elements to the buffer of `key`
+    pub async fn push(&self, key: K, value: E) {
+        self.partitions.push(key, value).await;
+    }
+
+    /// Manual trigger of every key
+    pub async fn trigger(&self) {
+        self.partitions.trigger().await;
+    }
+
+    /// Manual trigger of `key`
+    pub async fn trigger_key(&self, key: &K) {
+        self.partitions.trigger_key(key).await;
+    }
+
+    /// The number of elements of all keys
+    pub async fn len(&self) -> usize {
+        self.partitions.len().await
+    }
+
+    /// is empty
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// The number of keys with buffered elements
+    pub async fn keys(&self) -> usize {
+        self.partitions.buckets.lock().await.len()
+    }
+}
+
+impl<K, E, C> Drop for Keyed<K, E, C>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    E: fmt::Debug + Sync + Send + 'static,
+    C: fmt::Debug + Sync + Send + 'static,
+{
+    /// Flush every key and await the consumers, then wake the clock listener
+    /// so that it lets go of the buffers.
+    fn drop(&mut self) {
+        let _ = super::block_on(self.partitions.trigger());
+        self.partitions.opened.notify_one();
+    }
+}
+
+/// keyed buffer trigger builder
+pub struct Builder<K, E, C>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    E: fmt::Debug + Sync + Send + 'static,
+    C: fmt::Debug + Sync + Send + 'static,
+{
+    name: String,
+    defalut_container: fn() -> C,
+    accumulator: Accumulator<C, E>,
+    consumer: KeyedConsumer<K, C>,
+    max_len: usize,
+    interval: Option<Duration>,
+}
+
+impl<K, E, C> fmt::Debug for Builder<K, E, C>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    E: fmt::Debug + Sync + Send + 'static,
+    C: fmt::Debug + Sync + Send + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "name {}", self.name)
+    }
+}
+
+impl<K, E, C> Builder<K, E, C>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    E: fmt::Debug + Sync + Send + 'static,
+    C: fmt::Debug + Sync + Send + 'static,
+{
+    /// init
+    #[must_use]
+    #[allow(clippy::self_named_constructors)]
+    pub fn builder(defalut_container: fn() -> C) -> Self {
+        Self {
+            name: "anonymous".to_owned(),
+            defalut_container,
+            accumulator: Box::new(|_, _| {}),
+            consumer: Box::new(|_, _| Box::pin(async {})),
+            max_len: usize::MAX,
+            interval: None,
+        }
+    }
+
+    /// set `name`
+    #[must_use]
+    pub fn name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// set `accumulator`
+    #[must_use]
+    pub fn accumulator<F>(mut self, accumulator: F) -> Self
+    where
+        F: Fn(&mut C, E) + Send + Sync + 'static,
+    {
+        self.accumulator = Box::new(accumulator);
+        self
+    }
+
+    /// set `consumer`, it receives the key with its container
+    #[must_use]
+    pub fn consumer<F>(mut self, consumer: F) -> Self
+    where
+        F: Fn(K, C) + Send + Sync + 'static,
+    {
+        self.consumer = Box::new(move |k, c| {
+            consumer(k, c);
+            Box::pin(async {})
+        });
+        self
+    }
+
+    /// set an async `consumer`, it receives the key with its container
+    #[must_use]
+    pub fn async_consumer<F, Fut>(mut self, consumer: F) -> Self
+    where
+        F: Fn(K, C) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.consumer = Box::new(move |k, c| Box::pin(consumer(k, c)));
+        self
+    }
+
+    /// set `max_len`, per key
+    #[must_use]
+    pub const fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// set `interval`, per key
+    #[must_use]
+    pub const fn interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// `build`
+    ///
+    /// # Panics
+    ///
+    /// If `interval` is set and this is called outside of a tokio runtime,
+    /// the clock listener runs on that runtime.
+    #[must_use]
+    pub fn build(self) -> Keyed<K, E, C> {
+        let partitions = Partitions {
+            name: self.name,
+            buckets: Mutex::new(HashMap::new()),
+            opened: Notify::new(),
+            defalut_container: self.defalut_container,
+            accumulator: self.accumulator,
+            consumer: self.consumer,
+            max_len: self.max_len,
+            interval: self.interval,
+        };
+        let partitions = if let Some(interval) = self.interval {
+            let handle = Handle::current();
+            lifetime_thread::spawn(partitions, move |inner| {
+                drop(handle.spawn(async move {
+                    while let Some(p) = inner.get() {
+                        p.listen_clock_trigger(interval).await;
+                    }
+                }));
+            })
+        } else {
+            lifetime_thread::spawn(partitions, |_| {})
+        };
+        Keyed { partitions }
+    }
+}
@@ -1,8 +1,40 @@
+use std::{future::Future, thread};
+use tokio::runtime;
+
 pub(crate) mod general;
+pub(crate) mod keyed;
 pub(crate) mod simple;
 
 pub use general::builder::Builder as GeneralBuilder;
 pub use general::General;
 
+pub use keyed::Builder as KeyedBuilder;
+pub use keyed::Keyed;
+
 pub use simple::Builder as SimpleBuilder;
 pub use simple::Simple;
+
+/// Run `fut` to completion from a `Drop`, `None` if it panicked.
+///
+/// It runs on a temporary runtime on a scoped thread, because the current
+/// runtime, if any, may be shutting down and would never poll a spawned task.
+pub(crate) fn block_on<F>(fut: F) -> Option<F::Output>
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    thread::scope(|s| {
+        s.spawn(
+            || match runtime::Builder::new_current_thread().enable_all().build() {
+                Ok(rt) => Some(rt.block_on(fut)),
+                Err(e) => {
+                    log::error!("cannot start a runtime to flush on drop: {e}");
+                    None
+                }
+            },
+        )
+        .join()
+        .ok()
+        .flatten()
+    })
+}
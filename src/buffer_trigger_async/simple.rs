@@ -1,22 +1,16 @@
 use super::general::{self, General};
+use crate::{
+    BatchInfo, Clock, ConsumerError, DeadLetter, Error, OverflowPolicy, Payload, PushError,
+    RetryPolicy, ShutdownReport, TimerMode, TimerWheel, Wal,
+};
 use lifetime_thread::Outer;
-use std::{fmt, mem, time::Duration};
-#[derive(Debug)]
-struct Payload<C>
-where
-    C: fmt::Debug,
-{
-    len: usize,
-    container: C,
-    defalut_container: fn() -> C,
-}
-
+use std::{fmt, future::Future, time::Duration};
 pub struct Simple<E, C>
 where
     E: fmt::Debug + Sync + Send + 'static,
     C: fmt::Debug + Sync + Send + 'static,
 {
-    general: Outer<General<E, C, Payload<C>>>,
+    general: Outer<General<E, C, Payload<E, C>>>,
 }
 
 impl<E, C> Simple<E, C>
@@ -30,37 +24,83 @@ where
     pub async fn len(&self) -> usize {
         self.general.len().await
     }
+    /// The total weight of the elements, 0 without a `weigher`
+    pub async fn weight(&self) -> usize {
+        self.general.weight().await
+    }
     pub async fn push(&self, value: E) {
-        self.general.push(value).await
+        self.general.push(value).await;
+    }
+    /// add elements, hands the element back if the buffer is at `capacity` or closed
+    ///
+    /// # Errors
+    ///
+    /// `PushError::Full` if the buffer is at `capacity` with `Block` or `Reject`,
+    /// `PushError::Closed` once the trigger has been shut down,
+    /// `PushError::Wal` if the element could not be appended to the write-ahead log.
+    pub async fn try_push(&self, value: E) -> Result<(), PushError<E>> {
+        self.general.try_push(value).await
     }
     pub async fn trigger(&self) {
-        self.general.trigger().await
+        self.general.trigger().await;
+    }
+    /// Manual trigger, reports why the batch was not consumed
+    ///
+    /// # Errors
+    ///
+    /// `Error::ConsumerFailed` if the consumer gave up on the batch.
+    pub async fn try_trigger(&self) -> Result<(), Error> {
+        self.general.try_trigger().await
+    }
+    /// Number of elements pushed but not buffered: dropped by the
+    /// `overflow_policy`, pushed after `shutdown`, or lost to the write-ahead log.
+    #[must_use]
+    pub fn dropped(&self) -> u64 {
+        self.general.dropped()
+    }
+
+    /// Number of batches lost to a panicking consumer.
+    #[must_use]
+    pub fn panics(&self) -> u64 {
+        self.general.panics()
+    }
+    /// Stop accepting elements, flush the buffered ones and wait for the clock
+    /// listener to stop, for at most `timeout` altogether.
+    pub async fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        self.general.shutdown(timeout).await
     }
     pub async fn listen_clock_trigger(&self) {
-        self.general.listen_clock_trigger().await
+        self.general.listen_clock_trigger().await;
+    }
+}
+
+impl<E, C> Drop for Simple<E, C>
+where
+    E: fmt::Debug + Sync + Send,
+    C: fmt::Debug + Sync + Send,
+{
+    /// Stop the clock listener, so that the buffered elements are flushed now
+    /// rather than once the listener wakes up.
+    fn drop(&mut self) {
+        self.general.close();
     }
 }
 
 pub struct Builder<E, C>
 where
-    E: fmt::Debug,
-    C: fmt::Debug,
+    E: fmt::Debug + Sync + Send,
+    C: fmt::Debug + Sync + Send,
 {
-    name: String,
-    defalut_container: fn() -> C,
-    accumulator: fn(&mut C, E),
-    consumer: fn(C),
-    max_len: usize,
-    interval: Option<Duration>,
+    general: general::builder::Builder<E, C, Payload<E, C>>,
 }
 
 impl<E, C> fmt::Debug for Builder<E, C>
 where
-    E: fmt::Debug,
-    C: fmt::Debug,
+    E: fmt::Debug + Sync + Send,
+    C: fmt::Debug + Sync + Send,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "name {}", self.name)
+        self.general.fmt(f)
     }
 }
 
@@ -70,79 +110,203 @@ where
     C: fmt::Debug + Sync + Send,
 {
     /// init
+    #[must_use]
+    #[allow(clippy::self_named_constructors)]
     pub fn builder(defalut_container: fn() -> C) -> Self {
         Self {
-            name: "anonymous".to_owned(),
-            defalut_container,
-            accumulator: |_, _| {},
-            consumer: |_| {},
-            max_len: std::usize::MAX,
-            interval: None,
+            general: general::builder::Builder::builder(Payload::new(defalut_container, |_, _| {})),
         }
     }
 
     /// set `name`
     #[must_use]
     pub fn name(mut self, name: String) -> Self {
-        self.name = name;
+        self.general = self.general.name(name);
         self
     }
 
     /// set `accumulator`
-    pub fn accumulator(mut self, accumulator: fn(&mut C, E)) -> Self {
-        self.accumulator = accumulator;
+    #[must_use]
+    pub fn accumulator<F>(mut self, accumulator: F) -> Self
+    where
+        F: Fn(&mut C, E) + Send + Sync + 'static,
+    {
+        self.general.storage_mut().accumulator = Box::new(accumulator);
         self
     }
 
     /// set `consumer`
-    pub fn consumer(mut self, consumer: fn(C)) -> Self {
-        self.consumer = consumer;
+    #[must_use]
+    pub fn consumer<F>(mut self, consumer: F) -> Self
+    where
+        F: Fn(C) + Send + Sync + 'static,
+    {
+        self.general = self.general.consumer(consumer);
+        self
+    }
+
+    /// set an async `consumer`, `trigger` awaits the returned future
+    #[must_use]
+    pub fn async_consumer<F, Fut>(mut self, consumer: F) -> Self
+    where
+        F: Fn(C) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.general = self.general.async_consumer(consumer);
+        self
+    }
+
+    /// set an async `consumer`, it also receives the metadata of the batch
+    #[must_use]
+    pub fn async_consumer_with_info<F, Fut>(mut self, consumer: F) -> Self
+    where
+        F: Fn(C, BatchInfo) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.general = self.general.async_consumer_with_info(consumer);
+        self
+    }
+
+    /// set a fallible async `consumer`, failed batches are retried according to `retry_policy`
+    ///
+    /// A failed consumer hands the container back with the error, for the next attempt.
+    #[must_use]
+    pub fn try_consumer<F, Fut, Err>(mut self, consumer: F) -> Self
+    where
+        F: Fn(C) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), (C, Err)>> + Send + 'static,
+        Err: Into<ConsumerError>,
+    {
+        self.general = self.general.try_consumer(consumer);
+        self
+    }
+
+    /// set `retry_policy`
+    #[must_use]
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.general = self.general.retry_policy(retry_policy);
+        self
+    }
+
+    /// set `dead_letter`, it receives the batches that exhausted their retries
+    #[must_use]
+    pub fn dead_letter<D>(mut self, dead_letter: D) -> Self
+    where
+        D: DeadLetter<C> + 'static,
+    {
+        self.general = self.general.dead_letter(dead_letter);
         self
     }
 
     /// set `max_len`
     #[must_use]
     pub fn max_len(mut self, max_len: usize) -> Self {
-        self.max_len = max_len;
+        self.general = self.general.max_len(max_len);
+        self
+    }
+
+    /// set `weigher`, it gives the weight of an element, e.g. its size in bytes
+    #[must_use]
+    pub fn weigher<F>(mut self, weigher: F) -> Self
+    where
+        F: Fn(&E) -> usize + Send + Sync + 'static,
+    {
+        self.general = self.general.weigher(weigher);
+        self
+    }
+
+    /// set `max_weight`, a batch is flushed before an element would exceed it
+    #[must_use]
+    pub fn max_weight(mut self, max_weight: usize) -> Self {
+        self.general = self.general.max_weight(max_weight);
+        self
+    }
+
+    /// set `capacity`
+    #[must_use]
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.general = self.general.capacity(capacity);
+        self
+    }
+
+    /// set `overflow_policy`
+    #[must_use]
+    pub fn overflow_policy(mut self, overflow_policy: OverflowPolicy<C, E>) -> Self {
+        self.general = self.general.overflow_policy(overflow_policy);
+        self
+    }
+
+    /// set `on_drop`, it receives the elements pushed but not buffered, see `dropped`
+    #[must_use]
+    pub fn on_drop<F>(mut self, on_drop: F) -> Self
+    where
+        F: Fn(E) + Send + Sync + 'static,
+    {
+        self.general = self.general.on_drop(on_drop);
+        self
+    }
+
+    /// set `on_panic`, it receives the batches lost to a panicking consumer
+    #[must_use]
+    pub fn on_panic<F>(mut self, on_panic: F) -> Self
+    where
+        F: Fn(&BatchInfo, &str) + Send + Sync + 'static,
+    {
+        self.general = self.general.on_panic(on_panic);
         self
     }
 
     /// set `interval`
     #[must_use]
     pub fn interval(mut self, interval: Duration) -> Self {
-        self.interval = Some(interval);
+        self.general = self.general.interval(interval);
+        self
+    }
+
+    /// set `timer_mode`, how `interval` is measured
+    #[must_use]
+    pub fn timer_mode(mut self, timer_mode: TimerMode) -> Self {
+        self.general = self.general.timer_mode(timer_mode);
+        self
+    }
+
+    /// set `timer`, the windows are flushed by this shared wheel instead of a
+    /// listener task of the trigger
+    #[must_use]
+    pub fn timer(mut self, timer: TimerWheel) -> Self {
+        self.general = self.general.timer(timer);
+        self
+    }
+
+    /// set `clock`, the source of time of the trigger, a `ManualClock` also
+    /// takes over the flushes of `timer`
+    #[must_use]
+    pub fn clock<K>(mut self, clock: K) -> Self
+    where
+        K: Clock + 'static,
+    {
+        self.general = self.general.clock(clock);
+        self
+    }
+
+    /// set `wal`, every element is appended to this write-ahead log before
+    /// `push` returns, `build` replays what a previous process left in it
+    #[must_use]
+    pub fn wal(mut self, wal: Wal<E>) -> Self {
+        self.general = self.general.wal(wal);
         self
     }
 
     /// `build`
+    ///
+    /// # Panics
+    ///
+    /// If `interval` is set and this is called outside of a tokio runtime,
+    /// the clock listener runs on that runtime.
     #[must_use]
     pub fn build(self) -> Simple<E, C> {
-        let payload = Payload {
-            container: (self.defalut_container)(),
-            defalut_container: self.defalut_container,
-            len: 0,
-        };
-
-        let mut general = general::builder::Builder::builder();
-        if let Some(t) = self.interval {
-            general = general.interval(t);
+        Simple {
+            general: self.general.build(),
         }
-        let general = general
-            .consumer(self.consumer)
-            .max_len(self.max_len)
-            .payload(payload)
-            .get_len(|p| p.as_ref().unwrap().len)
-            .incr_len(|p| p.as_mut().unwrap().len += 1)
-            .clear_len(|p| p.as_mut().unwrap().len = 0)
-            .get_container(|p| &mut p.as_mut().unwrap().container)
-            .get_and_clear_container(|p| {
-                let mut new_container = (p.as_ref().unwrap().defalut_container)();
-                mem::swap(&mut new_container, &mut p.as_mut().unwrap().container);
-                new_container
-            })
-            .accumulator(self.accumulator)
-            .build();
-
-        Simple { general }
     }
 }
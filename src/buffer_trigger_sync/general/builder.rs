@@ -1,35 +1,56 @@
-use super::{General, Locker};
+use super::{Consumer, Expiry, General, Locker, OnDrop, OnPanic, Space, Weigher};
+use crate::timer_mode::next_tick;
+use crate::{
+    BatchInfo, Clock, ConsumerError, DeadLetter, FlushReason, OverflowPolicy, RetryPolicy, Storage,
+    SystemClock, TimerMode, TimerWheel, Wal,
+};
 use lifetime_thread::Outer;
-use std::sync::{mpsc, Mutex, RwLock};
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64},
+    mpsc, Arc, Mutex, RwLock,
+};
 use std::{fmt, time::Duration};
 /// general buffer trigger builer
-pub struct Builder<E, C, P>
+pub struct Builder<E, C, S>
 where
-    P: fmt::Debug + Send,
+    S: Storage<E, Batch = C>,
     E: fmt::Debug + Send,
     C: fmt::Debug + Send,
 {
-    payload: Option<P>,
+    storage: S,
     name: String,
     /// The function executed after the trigger condition is met.
-    consumer: fn(C),
+    consumer: Consumer<C>,
+    /// How failed batches are retried.
+    retry_policy: RetryPolicy,
+    /// Where batches go once they exhausted their retries.
+    dead_letter: Option<Box<dyn DeadLetter<C>>>,
     /// how many elements are exceeded
     max_len: usize,
+    weigher: Option<Weigher<E>>,
+    /// how much weight is exceeded
+    max_weight: usize,
+    /// How many elements may be buffered or being consumed at once.
+    capacity: usize,
+    /// What `push` does when the buffer is at `capacity`.
+    overflow_policy: OverflowPolicy<C, E>,
+    on_drop: Option<OnDrop<E>>,
+    on_panic: Option<OnPanic>,
     /// The maximum time to wait after an element is saved.
     interval: Option<Duration>,
-    /// Number of container elements
-    get_len: fn(&Option<P>) -> usize,
-    incr_len: fn(&mut Option<P>),
-    clear_len: fn(&mut Option<P>),
-    get_container: fn(&mut Option<P>) -> &mut C,
-    /// accumulator function
-    accumulator: fn(&mut C, E),
-    get_and_clear_container: fn(&mut Option<P>) -> C,
+    /// How `interval` is measured.
+    timer_mode: TimerMode,
+    /// The shared wheel that flushes the windows instead of a listener.
+    timer: Option<TimerWheel>,
+    /// The source of time.
+    clock: Arc<dyn Clock>,
+    /// Where elements are logged before `push` returns.
+    wal: Option<Wal<E>>,
 }
 
-impl<E, C, P> fmt::Debug for Builder<E, C, P>
+impl<E, C, S> fmt::Debug for Builder<E, C, S>
 where
-    P: fmt::Debug + Send,
+    S: Storage<E, Batch = C>,
     E: fmt::Debug + Send,
     C: fmt::Debug + Send,
 {
@@ -38,127 +59,295 @@ where
     }
 }
 
-impl<E, C, P> Builder<E, C, P>
+impl<E, C, S> Builder<E, C, S>
 where
-    P: fmt::Debug + Send,
+    S: Storage<E, Batch = C>,
     E: fmt::Debug + Send,
     C: fmt::Debug + Send,
 {
-    /// init
+    /// init, the elements are kept in `storage`
     #[must_use]
-    pub fn builder() -> Self {
+    #[allow(clippy::self_named_constructors)]
+    pub fn builder(storage: S) -> Self {
         Self {
-            payload: None,
+            storage,
             name: "anonymous".to_owned(),
-            get_len: |_| 1,
-            incr_len: |_| {},
-            clear_len: |_| {},
-            get_container: |_| panic!(),
-            accumulator: |_, _| {},
-            get_and_clear_container: |_| panic!(),
-            consumer: |_| {},
-            max_len: std::usize::MAX,
+            consumer: Box::new(|_, _| Ok(())),
+            retry_policy: RetryPolicy::default(),
+            dead_letter: None,
+            max_len: usize::MAX,
+            weigher: None,
+            max_weight: usize::MAX,
+            capacity: usize::MAX,
+            overflow_policy: OverflowPolicy::Block,
+            on_drop: None,
+            on_panic: None,
             interval: None,
+            timer_mode: TimerMode::MaxAge,
+            timer: None,
+            clock: Arc::new(SystemClock),
+            wal: None,
         }
     }
 
     /// set `name`
+    #[must_use]
     pub fn name(mut self, name: String) -> Self {
         self.name = name;
         self
     }
 
-    /// set `get_len`
-    pub fn get_len(mut self, get_len: fn(&Option<P>) -> usize) -> Self {
-        self.get_len = get_len;
+    /// set `consumer`
+    #[must_use]
+    pub fn consumer<F>(mut self, consumer: F) -> Self
+    where
+        F: Fn(C) + Send + Sync + 'static,
+    {
+        self.consumer = Box::new(move |c, _| {
+            consumer(c);
+            Ok(())
+        });
         self
     }
 
-    /// set `incr_len`
-    pub fn incr_len(mut self, incr_len: fn(&mut Option<P>)) -> Self {
-        self.incr_len = incr_len;
+    /// set `consumer`, it also receives the metadata of the batch
+    #[must_use]
+    pub fn consumer_with_info<F>(mut self, consumer: F) -> Self
+    where
+        F: Fn(C, &BatchInfo) + Send + Sync + 'static,
+    {
+        self.consumer = Box::new(move |c, info| {
+            consumer(c, info);
+            Ok(())
+        });
         self
     }
-    /// set `incr_len`
-    pub fn clear_len(mut self, clear_len: fn(&mut Option<P>)) -> Self {
-        self.clear_len = clear_len;
+
+    /// set a fallible `consumer`, failed batches are retried according to `retry_policy`
+    ///
+    /// A failed consumer hands the container back with the error, for the next attempt.
+    #[must_use]
+    pub fn try_consumer<F, Err>(mut self, consumer: F) -> Self
+    where
+        F: Fn(C) -> Result<(), (C, Err)> + Send + Sync + 'static,
+        Err: Into<ConsumerError>,
+    {
+        self.consumer = Box::new(move |c, _| consumer(c).map_err(|(c, e)| (c, e.into())));
         self
     }
 
-    /// set `get_container`
-    pub fn get_container(mut self, get_container: fn(&mut Option<P>) -> &mut C) -> Self {
-        self.get_container = get_container;
+    /// set `retry_policy`
+    #[must_use]
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
         self
     }
 
-    /// set `get_and_clear_container`
-    pub fn get_and_clear_container(
-        mut self,
-        get_and_clear_container: fn(&mut Option<P>) -> C,
-    ) -> Self {
-        self.get_and_clear_container = get_and_clear_container;
+    /// set `dead_letter`, it receives the batches that exhausted their retries
+    #[must_use]
+    pub fn dead_letter<D>(mut self, dead_letter: D) -> Self
+    where
+        D: DeadLetter<C> + 'static,
+    {
+        self.dead_letter = Some(Box::new(dead_letter));
         self
     }
 
-    /// set `accumulator`
-    pub fn accumulator(mut self, accumulator: fn(&mut C, E)) -> Self {
-        self.accumulator = accumulator;
+    /// set `max_len`
+    #[must_use]
+    pub const fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
         self
     }
 
-    /// set `consumer`
-    pub fn consumer(mut self, consumer: fn(C)) -> Self {
-        self.consumer = consumer;
+    /// set `weigher`, it gives the weight of an element, e.g. its size in bytes
+    #[must_use]
+    pub fn weigher<F>(mut self, weigher: F) -> Self
+    where
+        F: Fn(&E) -> usize + Send + Sync + 'static,
+    {
+        self.weigher = Some(Box::new(weigher));
         self
     }
 
-    /// set `max_len`
-    pub fn max_len(mut self, max_len: usize) -> Self {
-        self.max_len = max_len;
+    /// set `max_weight`, a batch is flushed before an element would exceed it
+    #[must_use]
+    pub const fn max_weight(mut self, max_weight: usize) -> Self {
+        self.max_weight = max_weight;
+        self
+    }
+
+    /// set `capacity`, `push` blocks while this many elements are buffered or being consumed
+    ///
+    /// A batch that takes every slot is flushed with `FlushReason::Capacity`,
+    /// as nothing else would release them.
+    #[must_use]
+    pub const fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// set `overflow_policy`
+    #[must_use]
+    pub const fn overflow_policy(mut self, overflow_policy: OverflowPolicy<C, E>) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// set `on_drop`, it receives the elements pushed but not buffered, see `dropped`
+    #[must_use]
+    pub fn on_drop<F>(mut self, on_drop: F) -> Self
+    where
+        F: Fn(E) + Send + Sync + 'static,
+    {
+        self.on_drop = Some(Box::new(on_drop));
+        self
+    }
+
+    /// set `on_panic`, it receives the batches lost to a panicking consumer
+    #[must_use]
+    pub fn on_panic<F>(mut self, on_panic: F) -> Self
+    where
+        F: Fn(&BatchInfo, &str) + Send + Sync + 'static,
+    {
+        self.on_panic = Some(Box::new(on_panic));
         self
     }
 
     /// set `interval`
-    pub fn interval(mut self, interval: Duration) -> Self {
+    #[must_use]
+    pub const fn interval(mut self, interval: Duration) -> Self {
         self.interval = Some(interval);
         self
     }
 
-    /// set `interval`
-    pub fn payload(mut self, payload: P) -> Self {
-        self.payload = Some(payload);
+    /// set `timer_mode`, how `interval` is measured
+    #[must_use]
+    pub const fn timer_mode(mut self, timer_mode: TimerMode) -> Self {
+        self.timer_mode = timer_mode;
+        self
+    }
+
+    /// set `timer`, the windows are flushed by this shared wheel instead of a
+    /// listener thread of the trigger, the consumer runs on a thread of its own
+    #[must_use]
+    pub fn timer(mut self, timer: TimerWheel) -> Self {
+        self.timer = Some(timer);
         self
     }
 
-    /// `build`
-    pub fn build(self) -> Outer<General<E, C, P>> {
+    /// set `clock`, the source of time of the trigger, a `ManualClock` also
+    /// takes over the flushes of `timer`
+    #[must_use]
+    pub fn clock<K>(mut self, clock: K) -> Self
+    where
+        K: Clock + 'static,
+    {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// set `wal`, every element is appended to this write-ahead log before
+    /// `push` returns, `build` replays what a previous process left in it
+    #[must_use]
+    pub fn wal(mut self, wal: Wal<E>) -> Self {
+        self.wal = Some(wal);
+        self
+    }
+
+    /// The storage, for the builders that fill it in.
+    pub(crate) const fn storage_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    /// `build`, the elements left in the `wal` are consumed before it returns
+    pub fn build(self) -> Outer<General<E, C, S>> {
         let (sender, receiver) = mpsc::channel();
+        let (done, listener) = mpsc::channel::<()>();
+        let clock = self.clock;
+        let wheel = self.timer;
+        let timer = self
+            .interval
+            .and_then(|_| clock.timer().or_else(|| wheel.map(|wheel| wheel.timer())));
+        // the elements left in the write-ahead log make up the first batch
+        let (mut storage, mut weight) = (self.storage, 0_usize);
+        let recovered = self.wal.as_ref().map_or_else(Vec::new, Wal::recover);
+        let replayed = recovered.len();
+        for e in recovered {
+            weight = weight.saturating_add(self.weigher.as_ref().map_or(0, |w| w(&e)));
+            storage.push(e);
+        }
         let general = General {
             name: self.name,
             locker: RwLock::new(Locker {
-                get_len: self.get_len,
-                incr_len: self.incr_len,
-                clear_len: self.clear_len,
-                get_container: self.get_container,
-                get_and_clear_container: self.get_and_clear_container,
-                accumulator: self.accumulator,
-                clock: false,
-                payload: self.payload,
+                storage,
+                deadline: None,
+                pushed: replayed,
+                weight,
+                first_push_at: (replayed > 0).then(|| clock.now()),
+                batches: 0,
+                clock: clock.clone(),
             }),
             consumer: self.consumer,
+            retry_policy: self.retry_policy,
+            dead_letter: self.dead_letter,
             max_len: self.max_len,
+            weigher: self.weigher,
+            max_weight: self.max_weight,
+            capacity: self.capacity,
+            space: if self.capacity == usize::MAX {
+                None
+            } else {
+                Some(Space::new(self.capacity))
+            },
+            replayed,
+            overflow_policy: self.overflow_policy,
+            on_drop: self.on_drop,
+            dropped: AtomicU64::new(0),
+            on_panic: self.on_panic,
+            panics: AtomicU64::new(0),
             interval: self.interval,
+            closed: AtomicBool::new(false),
             sender: Mutex::new(sender),
             receiver: Mutex::new(receiver),
+            listener: (self.interval.is_some() && timer.is_none()).then(|| Mutex::new(listener)),
+            timer: timer.clone(),
+            timer_mode: self.timer_mode,
+            clock: clock.clone(),
+            wal: self.wal,
         };
-        if self.interval.is_some() {
-            lifetime_thread::spawn(general, |inner| {
+        let general = if let Some(timer) = timer {
+            // register before `build` returns, so that every deadline finds its trigger
+            let (registered, inner) = mpsc::channel();
+            let general = lifetime_thread::spawn(general, move |inner| {
+                let _ = registered.send(inner);
+            });
+            if let Ok(inner) = inner.recv() {
+                timer.register(Expiry {
+                    inner: Arc::new(Mutex::new(inner)),
+                });
+            }
+            if let (Some(_), Some(dur)) = (self.timer_mode.ticker(), self.interval) {
+                timer.schedule(next_tick(&*clock, dur), 0);
+            }
+            general
+        } else if self.interval.is_some() {
+            lifetime_thread::spawn(general, move |inner| {
                 while let Some(g) = inner.get() {
-                    g.listen_clock_trigger()
+                    if !g.clock_tick() {
+                        // closed by `shutdown` or a dropped `Simple`
+                        let _ = g.flush(FlushReason::Drop);
+                        break;
+                    }
                 }
+                drop(done);
             })
         } else {
             lifetime_thread::spawn(general, |_| {})
+        };
+        if replayed > 0 {
+            let _ = general.flush(FlushReason::Replay);
         }
+        general
     }
 }
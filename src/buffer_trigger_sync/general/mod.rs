@@ -1,58 +1,178 @@
 use super::BufferTrigger;
+use crate::error::panic_message;
+use crate::timer::{Target, Timer};
+use crate::timer_mode::next_tick;
+use crate::{
+    BatchInfo, Clock, ConsumerError, DeadLetter, Error, FlushReason, OverflowPolicy, PushError,
+    RetryPolicy, ShutdownReport, Storage, TimerMode, Wal,
+};
+use lifetime_thread::Inner;
 use std::sync::{
-    mpsc::{Receiver, Sender},
-    Mutex, RwLock,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    mpsc::{Receiver, RecvTimeoutError, Sender},
+    Arc, Condvar, Mutex, PoisonError, RwLock, TryLockError,
+};
+use std::{
+    any::Any,
+    fmt, mem,
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
 };
-use std::thread;
-use std::{fmt, time::Duration};
 
 pub mod builder;
-struct Locker<E, C, P>
-where
-    P: fmt::Debug,
-    E: fmt::Debug,
-    C: fmt::Debug,
-{
-    payload: Option<P>,
-    /// Whether the timed task has been set
-    clock: bool,
-    /// Number of container elements
-    get_len: fn(&Option<P>) -> usize,
 
-    incr_len: fn(&mut Option<P>),
+/// The function executed after the trigger condition is met.
+///
+/// A failed consumer hands the container back so that it can be retried.
+pub type Consumer<C> = Box<dyn Fn(C, &BatchInfo) -> Result<(), (C, ConsumerError)> + Send + Sync>;
+/// Receives the elements pushed but not buffered.
+pub type OnDrop<E> = Box<dyn Fn(E) + Send + Sync>;
+/// Receives the batch metadata and the message of a panicking consumer.
+pub type OnPanic = Box<dyn Fn(&BatchInfo, &str) + Send + Sync>;
+/// The weight of an element, e.g. its size in bytes.
+pub type Weigher<E> = Box<dyn Fn(&E) -> usize + Send + Sync>;
 
-    clear_len: fn(&mut Option<P>),
+struct Locker<S> {
+    /// The elements of the current batch.
+    storage: S,
+    /// When the current window expires, set by the first element of a batch
+    /// and moved on by each push in `TimerMode::Debounce`
+    deadline: Option<Instant>,
+    /// Elements pushed since the last trigger, their slots are released once consumed.
+    pushed: usize,
+    /// Total weight of the container elements
+    weight: usize,
+    /// When the first element of the container was pushed
+    first_push_at: Option<Instant>,
+    /// Number of batches taken so far, also the generation of the current window
+    batches: u64,
+    clock: Arc<dyn Clock>,
+}
+
+impl<S> Locker<S> {
+    /// Swap the container out, with the metadata of the batch.
+    fn take<E>(&mut self, name: &str, reason: FlushReason) -> (S::Batch, BatchInfo)
+    where
+        S: Storage<E>,
+    {
+        self.deadline = None;
+        self.weight = 0;
+        let flushed_at = self.clock.now();
+        let info = BatchInfo {
+            reason,
+            len: mem::take(&mut self.pushed),
+            batch_id: self.batches,
+            first_push_at: self.first_push_at.take().unwrap_or(flushed_at),
+            flushed_at,
+            trigger_name: name.to_owned(),
+        };
+        self.batches += 1;
+        (self.storage.take_batch(), info)
+    }
+}
 
-    get_container: fn(&mut Option<P>) -> &mut C,
-    /// accumulator function
-    accumulator: fn(&mut C, E),
-    get_and_clear_container: fn(&mut Option<P>) -> C,
+/// Free slots of a bounded buffer.
+struct Space {
+    free: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl Space {
+    const fn new(capacity: usize) -> Self {
+        Self {
+            free: Mutex::new(capacity),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Wait for a free slot and take it.
+    fn acquire(&self) {
+        let mut free = self.free.lock().unwrap_or_else(PoisonError::into_inner);
+        while *free == 0 {
+            free = self
+                .freed
+                .wait(free)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        *free -= 1;
+    }
+
+    /// Take a free slot if there is one.
+    fn try_acquire(&self) -> bool {
+        let mut free = self.free.lock().unwrap_or_else(PoisonError::into_inner);
+        if *free == 0 {
+            return false;
+        }
+        *free -= 1;
+        true
+    }
+
+    fn release(&self, n: usize) {
+        if n > 0 {
+            *self.free.lock().unwrap_or_else(PoisonError::into_inner) += n;
+            self.freed.notify_all();
+        }
+    }
 }
 
 /// General `BufferTrigger`
 ///
-/// Set your own container to store in the current service
-pub struct General<E, C, P>
+/// Set your own `Storage` to keep the elements in
+pub struct General<E, C, S>
 where
-    P: fmt::Debug + Send + 'static,
+    S: Storage<E, Batch = C> + 'static,
     E: fmt::Debug + Send + 'static,
     C: fmt::Debug + Send + 'static,
 {
     name: String,
-    locker: RwLock<Locker<E, C, P>>,
+    locker: RwLock<Locker<S>>,
     /// The function executed after the trigger condition is met.
-    consumer: fn(C),
+    consumer: Consumer<C>,
+    /// How failed batches are retried.
+    retry_policy: RetryPolicy,
+    /// Where batches go once they exhausted their retries.
+    dead_letter: Option<Box<dyn DeadLetter<C>>>,
     /// how many elements are exceeded
     max_len: usize,
+    weigher: Option<Weigher<E>>,
+    /// how much weight is exceeded, a batch is flushed before an element would exceed it
+    max_weight: usize,
+    /// How many elements may be buffered or being consumed at once.
+    capacity: usize,
+    space: Option<Space>,
+    /// Elements replayed from the write-ahead log into batch 0, they take no slot.
+    replayed: usize,
+    /// What `push` does when the buffer is at `capacity`.
+    overflow_policy: OverflowPolicy<C, E>,
+    on_drop: Option<OnDrop<E>>,
+    /// Number of elements pushed but not buffered: dropped by the
+    /// `overflow_policy`, pushed after `shutdown`, or lost to a poisoned lock
+    /// or to the write-ahead log.
+    dropped: AtomicU64,
+    on_panic: Option<OnPanic>,
+    /// Number of batches lost to a panicking consumer.
+    panics: AtomicU64,
     /// The maximum time to wait after an element is saved.
     interval: Option<Duration>,
+    /// Set by `shutdown` and `close`, elements pushed afterwards are dropped.
+    closed: AtomicBool,
     sender: Mutex<Sender<()>>,
     receiver: Mutex<Receiver<()>>,
+    /// Disconnected once the clock listener has stopped.
+    listener: Option<Mutex<Receiver<()>>>,
+    /// The shared wheel that flushes the windows instead of a listener.
+    timer: Option<Timer>,
+    /// How `interval` is measured.
+    timer_mode: TimerMode,
+    /// The source of time.
+    clock: Arc<dyn Clock>,
+    /// Where elements are logged before `push` returns.
+    wal: Option<Wal<E>>,
 }
 
-impl<E, C, P> fmt::Debug for General<E, C, P>
+impl<E, C, S> fmt::Debug for General<E, C, S>
 where
-    P: fmt::Debug + Send + 'static,
+    S: Storage<E, Batch = C> + 'static,
     E: fmt::Debug + Send + 'static,
     C: fmt::Debug + Send + 'static,
 {
@@ -61,90 +181,519 @@ where
     }
 }
 
-impl<E, C, P> super::BufferTrigger<E> for General<E, C, P>
+impl<E, C, S> super::BufferTrigger<E> for General<E, C, S>
 where
-    P: fmt::Debug + Send,
+    S: Storage<E, Batch = C>,
     E: fmt::Debug + Send,
     C: fmt::Debug + Send,
 {
     fn len(&self) -> usize {
-        if let Ok(c) = self.locker.read() {
-            (c.get_len)(&c.payload)
-        } else {
-            0
-        }
+        self.try_len().unwrap_or(0)
+    }
+
+    fn try_len(&self) -> Result<usize, Error> {
+        self.locker
+            .read()
+            .map(|c| c.storage.len())
+            .map_err(|_| Error::Poisoned)
     }
     fn push(&self, value: E) {
-        if let Ok(mut c) = self.locker.write() {
-            (c.incr_len)(&mut c.payload);
-            (c.accumulator)((c.get_container)(&mut c.payload), value);
-            if let (false, Some(dur)) = (c.clock, self.interval) {
-                c.clock = true;
-                match self.sender.lock() {
-                    Ok(sender) => {
-                        let sender = sender.clone();
-                        let _ = thread::spawn(move || {
-                            thread::sleep(dur);
-                            if let Err(e) = sender.send(()) {
-                                log::error!("auto clock trigger error {}", e);
-                            };
-                        });
-                    }
-                    Err(e) => {
-                        log::error!("{}", e);
+        if let Some(space) = &self.space {
+            match self.overflow_policy {
+                OverflowPolicy::Block => space.acquire(),
+                _ if space.try_acquire() => {}
+                // `try_push` hands a rejected element back, `push` cannot
+                OverflowPolicy::Reject | OverflowPolicy::DropNewest => {
+                    return self.drop_element(value)
+                }
+                OverflowPolicy::DropOldest(evict, peek) => {
+                    if let Err(e) = self.replace(evict, peek, value) {
+                        self.push_failed(e);
                     }
+                    return;
                 }
             }
         }
-        if self.len() >= self.max_len {
-            self.trigger()
+        if let Err(e) = self.accumulate(value) {
+            self.push_failed(e);
         }
     }
 
-    fn trigger(&self) {
-        if !self.is_empty() {
-            if let Ok(mut c) = self.locker.write() {
-                c.clock = false;
-                (c.clear_len)(&mut c.payload);
-                (self.consumer)((c.get_and_clear_container)(&mut c.payload));
+    fn try_push(&self, value: E) -> Result<(), PushError<E>> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(PushError::Closed(value));
+        }
+        if let Some(space) = &self.space {
+            if !space.try_acquire() {
+                match self.overflow_policy {
+                    OverflowPolicy::Block | OverflowPolicy::Reject => {
+                        return Err(PushError::Full(value))
+                    }
+                    OverflowPolicy::DropNewest => self.drop_element(value),
+                    OverflowPolicy::DropOldest(evict, peek) => {
+                        return self.replace(evict, peek, value)
+                    }
+                }
+                return Ok(());
             }
         }
+        self.accumulate(value)
+    }
+
+    fn trigger(&self) {
+        let _ = self.flush(FlushReason::Manual);
+    }
+
+    fn try_trigger(&self) -> Result<(), Error> {
+        self.flush(FlushReason::Manual)
     }
 
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    fn weight(&self) -> usize {
+        self.locker.read().map_or(0, |c| c.weight)
+    }
 }
-impl<E, C, P> General<E, C, P>
+impl<E, C, S> General<E, C, S>
 where
-    P: fmt::Debug + Send,
+    S: Storage<E, Batch = C>,
     E: fmt::Debug + Send,
     C: fmt::Debug + Send,
 {
-    /// start clock trigger listener
-    fn listen_clock_trigger(&self) {
-        log::info!("{:?} listen_clock_trigger", self);
-        while let Ok(recevier) = self.receiver.lock() {
-            if recevier.recv().is_ok() {
-                let clock = if let Ok(c) = self.locker.read() {
-                    c.clock
-                } else {
-                    false
-                };
-                if clock {
-                    self.trigger();
+    /// Add `value` to the container, its slot is released if it is handed back.
+    fn accumulate(&self, value: E) -> Result<(), PushError<E>> {
+        let weight = self.weigh(&value);
+        let mut guard = match self.locker.write() {
+            Ok(guard) if !self.closed.load(Ordering::Acquire) => guard,
+            Ok(_) => return Err(self.hand_back(PushError::Closed(value))),
+            Err(_) => {
+                log::error!("{self:?} buffer lock is poisoned");
+                return Err(self.hand_back(PushError::Poisoned(value)));
+            }
+        };
+        let c = &mut *guard;
+        // flush first rather than let the batch exceed `max_weight`
+        let overweight = (c.weight > 0 && c.weight.saturating_add(weight) > self.max_weight)
+            .then(|| c.take(&self.name, FlushReason::MaxWeight));
+        if let Err(e) = self.log(c.batches, &value) {
+            log::error!("{self:?} write-ahead log failed: {e}");
+            drop(guard);
+            if let Some(batch) = overweight {
+                let _ = self.consume_batch(batch);
+            }
+            return Err(self.hand_back(PushError::Wal(value)));
+        }
+        let now = self.clock.now();
+        let first_push_at = *c.first_push_at.get_or_insert(now);
+        c.storage.push(value);
+        c.pushed += 1;
+        c.weight = c.weight.saturating_add(weight);
+        let deadline = self
+            .interval
+            .and_then(|dur| self.timer_mode.deadline(first_push_at, now, dur));
+        if let Some(deadline) = deadline {
+            // a window opens, the listener may be waiting for longer than it lasts
+            match (c.deadline, &self.timer) {
+                (None, Some(timer)) => timer.schedule(deadline, c.batches),
+                (None, None) if self.listener.is_some() => {
+                    if let Ok(sender) = self.sender.lock() {
+                        let _ = sender.send(());
+                    }
+                }
+                _ => {}
+            }
+            c.deadline = Some(deadline);
+        }
+        // a full buffer is flushed, otherwise pushes would wait forever
+        let full = if c.storage.len() >= self.max_len {
+            Some(FlushReason::MaxLen)
+        } else if c.weight >= self.max_weight {
+            Some(FlushReason::MaxWeight)
+        } else if c.pushed >= self.capacity {
+            Some(FlushReason::Capacity)
+        } else {
+            None
+        };
+        drop(guard);
+        if let Some(batch) = overweight {
+            let _ = self.consume_batch(batch);
+        }
+        if let Some(reason) = full {
+            let _ = self.flush(reason);
+        }
+        Ok(())
+    }
+
+    /// Release the slot of an element that is handed back.
+    fn hand_back(&self, e: PushError<E>) -> PushError<E> {
+        if let Some(space) = &self.space {
+            space.release(1);
+        }
+        e
+    }
+
+    fn weigh(&self, value: &E) -> usize {
+        self.weigher.as_ref().map_or(0, |weigher| weigher(value))
+    }
+
+    /// Evict a buffered element to make room for `value`, without taking a slot.
+    ///
+    /// `value` is dropped if nothing can be evicted, and handed back if the
+    /// eviction cannot be logged, the batch is then left as it was.
+    fn replace(
+        &self,
+        evict: fn(&mut C) -> Option<E>,
+        peek: fn(&C) -> Option<&E>,
+        value: E,
+    ) -> Result<(), PushError<E>> {
+        let mut guard = match self.locker.write() {
+            Ok(guard) if !self.closed.load(Ordering::Acquire) => guard,
+            Ok(_) => return Err(PushError::Closed(value)),
+            Err(_) => {
+                log::error!("{self:?} buffer lock is poisoned");
+                return Err(PushError::Poisoned(value));
+            }
+        };
+        let c = &mut *guard;
+        // log the eviction first, so that a failure leaves the batch untouched
+        let dropped = match self.log_eviction(c.batches, c.storage.peek(peek), &value) {
+            Ok(true) => match c.storage.evict(evict) {
+                Some(evicted) => {
+                    c.weight = (c.weight + self.weigh(&value)).saturating_sub(self.weigh(&evicted));
+                    c.storage.push(value);
+                    evicted
                 }
+                None => value,
+            },
+            Ok(false) => value,
+            Err(e) => {
+                log::error!("{self:?} write-ahead log failed: {e}");
+                return Err(PushError::Wal(value));
             }
+        };
+        drop(guard);
+        self.drop_element(dropped);
+        Ok(())
+    }
+
+    /// Append `value` to the write-ahead log, if there is one.
+    fn log(&self, batch: u64, value: &E) -> std::io::Result<()> {
+        self.wal
+            .as_ref()
+            .map_or(Ok(()), |wal| wal.append(batch, value))
+    }
+
+    /// Log that the `peeked` element makes room for `value`, if there is a
+    /// write-ahead log. False if it must not be evicted, as it cannot be logged.
+    fn log_eviction(&self, batch: u64, peeked: Option<&E>, value: &E) -> std::io::Result<bool> {
+        match (&self.wal, peeked) {
+            (Some(wal), Some(evicted)) => wal.replace(batch, evicted, value).map(|()| true),
+            (Some(_), None) => Ok(false),
+            (None, _) => Ok(true),
+        }
+    }
+
+    /// Drop the element `push` could not buffer, `try_push` would hand it back.
+    fn push_failed(&self, e: PushError<E>) {
+        log::warn!("{self:?} push dropped an element: {e}");
+        self.drop_element(e.into_inner());
+    }
+
+    fn drop_element(&self, value: E) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        if let Some(on_drop) = &self.on_drop {
+            on_drop(value);
         }
     }
+
+    /// Number of elements pushed but not buffered: dropped by the
+    /// `overflow_policy`, pushed after `shutdown`, or lost to a poisoned lock
+    /// or to the write-ahead log.
+    #[must_use]
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Take the container out and consume it.
+    fn flush(&self, reason: FlushReason) -> Result<(), Error> {
+        // swap the container out under the lock, consume it after release
+        let batch = match self.locker.write() {
+            Ok(mut c) if c.storage.len() > 0 => c.take(&self.name, reason),
+            Ok(_) => return Ok(()),
+            Err(_) => {
+                log::error!("{self:?} buffer lock is poisoned");
+                return Err(Error::Poisoned);
+            }
+        };
+        self.consume_batch(batch)
+    }
+
+    /// Flush the window `window` once its deadline has passed, timers of older
+    /// windows are ignored. A `Ticker` on a wheel flushes and schedules its next tick instead.
+    fn expire(&self, window: u64) -> Result<(), Error> {
+        if let (Some(heartbeat), Some(dur), Some(timer)) =
+            (self.timer_mode.ticker(), self.interval, &self.timer)
+        {
+            timer.schedule(next_tick(&*self.clock, dur), window);
+            return self.tick(heartbeat);
+        }
+        let batch = match self.locker.write() {
+            Ok(mut c) if c.batches == window && c.storage.len() > 0 => match c.deadline {
+                Some(d) if d <= self.clock.now() => c.take(&self.name, FlushReason::Clock),
+                Some(d) => {
+                    // pushes moved a debounced deadline on, the listener picks it up by itself
+                    if let Some(timer) = &self.timer {
+                        timer.schedule(d, window);
+                    }
+                    return Ok(());
+                }
+                None => return Ok(()),
+            },
+            Ok(_) => return Ok(()),
+            Err(_) => {
+                log::error!("{self:?} buffer lock is poisoned");
+                return Err(Error::Poisoned);
+            }
+        };
+        self.consume_batch(batch)
+    }
+
+    /// Slots taken by the elements of a batch.
+    const fn slots(&self, info: &BatchInfo) -> usize {
+        if info.batch_id == 0 {
+            info.len.saturating_sub(self.replayed)
+        } else {
+            info.len
+        }
+    }
+
+    /// Consume a batch taken from the locker and release its slots.
+    fn consume_batch(&self, (container, info): (C, BatchInfo)) -> Result<(), Error> {
+        let consumed = self.consume(container, &info);
+        // a dead-lettered batch must not be replayed either
+        let dead_lettered =
+            self.dead_letter.is_some() && matches!(consumed, Err(Error::ConsumerFailed { .. }));
+        if let (true, Some(wal)) = (consumed.is_ok() || dead_lettered, &self.wal) {
+            wal.consumed(info.batch_id);
+        }
+        if let Some(space) = &self.space {
+            space.release(self.slots(&info));
+        }
+        consumed
+    }
+
+    /// Run the consumer, retrying with backoff until it succeeds or gives up.
+    fn consume(&self, mut container: C, info: &BatchInfo) -> Result<(), Error> {
+        let mut attempt = 1;
+        loop {
+            let consumed =
+                panic::catch_unwind(AssertUnwindSafe(|| (self.consumer)(container, info)));
+            let (c, e) = match consumed {
+                Ok(Ok(())) => return Ok(()),
+                Ok(Err(failed)) => failed,
+                Err(payload) => return Err(self.panicked(&*payload, info)),
+            };
+            if let Some(backoff) = self.retry_policy.next_backoff(attempt) {
+                log::warn!(
+                    "{self:?} consumer failed on attempt {attempt}, retry in {backoff:?}: {e}"
+                );
+                self.clock.sleep(backoff);
+                attempt += 1;
+                container = c;
+            } else {
+                self.retry_policy.give_up(&e, attempt);
+                let reason = e.to_string();
+                if let Some(dead_letter) = &self.dead_letter {
+                    dead_letter.dead_letter(c, e, attempt);
+                }
+                return Err(Error::ConsumerFailed {
+                    attempts: attempt,
+                    reason,
+                });
+            }
+        }
+    }
+
+    /// Count and report a panicking consumer, its batch is lost.
+    fn panicked(&self, payload: &(dyn Any + Send), info: &BatchInfo) -> Error {
+        let reason = panic_message(payload);
+        log::error!(
+            "{self:?} consumer panicked on batch {}: {reason}",
+            info.batch_id
+        );
+        self.panics.fetch_add(1, Ordering::Relaxed);
+        if let Some(on_panic) = &self.on_panic {
+            on_panic(info, &reason);
+        }
+        Error::ConsumerPanicked { reason }
+    }
+
+    /// Number of batches lost to a panicking consumer.
+    #[must_use]
+    pub fn panics(&self) -> u64 {
+        self.panics.load(Ordering::Relaxed)
+    }
+
+    /// Stop accepting elements, flush the buffered ones and wait at most
+    /// `timeout` for the clock listener to stop.
+    ///
+    /// Elements pushed afterwards are dropped. The consumer runs on the calling
+    /// thread and cannot be interrupted, so `timeout` bounds the wait for the listener.
+    #[must_use]
+    pub fn shutdown(&self, timeout: Duration) -> ShutdownReport {
+        let start = self.clock.now();
+        let mut guard = self.locker.write().unwrap_or_else(PoisonError::into_inner);
+        self.closed.store(true, Ordering::Release);
+        let c = &mut *guard;
+        let batch = (c.storage.len() > 0).then(|| c.take(&self.name, FlushReason::Shutdown));
+        drop(guard);
+        let mut report = ShutdownReport::default();
+        if let Some(batch) = batch {
+            let len = batch.1.len;
+            if self.consume_batch(batch).is_ok() {
+                report.flushed = len;
+            } else {
+                report.left = len;
+            }
+        }
+        self.close();
+        report.listener_stopped = self.join_listener(
+            timeout.saturating_sub(self.clock.now().saturating_duration_since(start)),
+        );
+        report.left += self.len();
+        report
+    }
+
+    /// Stop accepting elements and stop the clock listener without waiting,
+    /// the buffered elements are flushed by the listener as it exits, or when
+    /// the trigger is dropped.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        if let Some(timer) = &self.timer {
+            timer.cancel();
+        }
+        // wake the listener so that it sees `closed`
+        if let Ok(sender) = self.sender.lock() {
+            let _ = sender.send(());
+        }
+    }
+
+    /// Wait at most `timeout` for the clock listener to stop.
+    fn join_listener(&self, timeout: Duration) -> bool {
+        self.listener.as_ref().is_none_or(|listener| {
+            let listener = listener.lock().unwrap_or_else(PoisonError::into_inner);
+            matches!(
+                listener.recv_timeout(timeout),
+                Err(RecvTimeoutError::Disconnected)
+            )
+        })
+    }
+
+    /// Wait until the window deadline, or the next tick of a `Ticker`, or at
+    /// most an `interval` without either, and flush. False once the trigger is closed.
+    ///
+    /// The wait is bounded so that the listener notices when the trigger is dropped.
+    fn clock_tick(&self) -> bool {
+        let interval = self.interval.unwrap_or(Duration::from_secs(1));
+        let now = self.clock.now();
+        let ticker = self
+            .timer_mode
+            .ticker()
+            .map(|heartbeat| (heartbeat, next_tick(&*self.clock, interval)));
+        let window = self.window();
+        let wait = match (ticker, window) {
+            (Some((_, at)), _) | (None, Some((_, at))) => at.saturating_duration_since(now),
+            (None, None) => interval,
+        };
+        let tick = self
+            .receiver
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .recv_timeout(wait);
+        if self.closed.load(Ordering::Acquire) {
+            return false;
+        }
+        match tick {
+            Ok(()) | Err(RecvTimeoutError::Timeout) => {
+                if let Some((heartbeat, at)) = ticker {
+                    if at <= self.clock.now() {
+                        let _ = self.tick(heartbeat);
+                    }
+                } else if let Some((window, _)) = window {
+                    let _ = self.expire(window);
+                }
+                true
+            }
+            Err(RecvTimeoutError::Disconnected) => false,
+        }
+    }
+
+    /// Flush on a tick of a `Ticker`, an empty batch too with `heartbeat`.
+    fn tick(&self, heartbeat: bool) -> Result<(), Error> {
+        let batch = match self.locker.write() {
+            Ok(mut c) if heartbeat || c.storage.len() > 0 => c.take(&self.name, FlushReason::Clock),
+            Ok(_) => return Ok(()),
+            Err(_) => {
+                log::error!("{self:?} buffer lock is poisoned");
+                return Err(Error::Poisoned);
+            }
+        };
+        self.consume_batch(batch)
+    }
+
+    /// The generation and the deadline of the current window, if one is open.
+    fn window(&self) -> Option<(u64, Instant)> {
+        let c = self.locker.read().ok()?;
+        c.deadline.map(|d| (c.batches, d))
+    }
 }
-impl<E, C, P> Drop for General<E, C, P>
+impl<E, C, S> Drop for General<E, C, S>
 where
-    P: fmt::Debug + Send,
+    S: Storage<E, Batch = C>,
     E: fmt::Debug + Send,
     C: fmt::Debug + Send,
 {
     fn drop(&mut self) {
-        self.trigger();
+        let _ = self.flush(FlushReason::Drop);
+    }
+}
+
+/// A sync trigger registered with a `TimerWheel` or a `ManualClock`.
+struct Expiry<E, C, S>
+where
+    S: Storage<E, Batch = C> + 'static,
+    E: fmt::Debug + Send + 'static,
+    C: fmt::Debug + Send + 'static,
+{
+    /// Locked so that it can be shared without a `Sync` storage.
+    inner: Arc<Mutex<Inner<General<E, C, S>>>>,
+}
+
+impl<E, C, S> Target for Expiry<E, C, S>
+where
+    S: Storage<E, Batch = C>,
+    E: fmt::Debug + Send,
+    C: fmt::Debug + Send,
+{
+    fn expire(&self, window: u64) {
+        let inner = self.inner.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(g) = inner.get() {
+            let _ = g.expire(window);
+        }
+    }
+
+    fn fire(&self, window: u64) {
+        self.expire(window);
+    }
+
+    /// A trigger that is flushing is alive, the wheel thread does not wait for it.
+    fn is_alive(&self) -> bool {
+        match self.inner.try_lock() {
+            Ok(inner) => inner.get().is_some(),
+            Err(TryLockError::Poisoned(e)) => e.into_inner().get().is_some(),
+            Err(TryLockError::WouldBlock) => true,
+        }
     }
 }
@@ -0,0 +1,340 @@
+use crate::storage::Accumulator;
+use lifetime_thread::Outer;
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    mem,
+    sync::{Condvar, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// The buffered elements of one key.
+struct Bucket<C> {
+    container: C,
+    len: usize,
+    /// When the clock flushes this bucket.
+    deadline: Option<Instant>,
+}
+
+struct Partitions<K, E, C>
+where
+    K: Eq + Hash + Send + 'static,
+    E: fmt::Debug + Send + 'static,
+    C: fmt::Debug + Send + 'static,
+{
+    name: String,
+    buckets: Mutex<HashMap<K, Bucket<C>>>,
+    /// Notified when a bucket with a deadline is opened.
+    opened: Condvar,
+    defalut_container: fn() -> C,
+    accumulator: Accumulator<C, E>,
+    consumer: Box<dyn Fn(K, C) + Send + Sync>,
+    /// how many elements of a key are exceeded
+    max_len: usize,
+    /// The maximum time to wait after an element of a key is saved.
+    interval: Option<Duration>,
+}
+
+impl<K, E, C> fmt::Debug for Partitions<K, E, C>
+where
+    K: Eq + Hash + Send + 'static,
+    E: fmt::Debug + Send + 'static,
+    C: fmt::Debug + Send + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "name {}", self.name)
+    }
+}
+
+impl<K, E, C> Partitions<K, E, C>
+where
+    K: Eq + Hash + Send + 'static,
+    E: fmt::Debug + Send + 'static,
+    C: fmt::Debug + Send + 'static,
+{
+    fn lock(&self) -> MutexGuard<'_, HashMap<K, Bucket<C>>> {
+        self.buckets.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn push(&self, key: K, value: E) {
+        let mut buckets = self.lock();
+        if let Some(bucket) = buckets.get_mut(&key) {
+            (self.accumulator)(&mut bucket.container, value);
+            bucket.len += 1;
+            if bucket.len < self.max_len {
+                return;
+            }
+        } else {
+            // a new window opens with its first element
+            let mut bucket = Bucket {
+                container: (self.defalut_container)(),
+                len: 1,
+                deadline: self.interval.map(|interval| Instant::now() + interval),
+            };
+            (self.accumulator)(&mut bucket.container, value);
+            if bucket.len < self.max_len {
+                if bucket.deadline.is_some() {
+                    self.opened.notify_one();
+                }
+                buckets.insert(key, bucket);
+            } else {
+                drop(buckets);
+                (self.consumer)(key, bucket.container);
+            }
+            return;
+        }
+        let full = buckets.remove_entry(&key);
+        drop(buckets);
+        if let Some((key, bucket)) = full {
+            (self.consumer)(key, bucket.container);
+        }
+    }
+
+    fn trigger(&self) {
+        let buckets = mem::take(&mut *self.lock());
+        for (key, bucket) in buckets {
+            (self.consumer)(key, bucket.container);
+        }
+    }
+
+    fn trigger_key(&self, key: &K) {
+        let bucket = self.lock().remove_entry(key);
+        if let Some((key, bucket)) = bucket {
+            (self.consumer)(key, bucket.container);
+        }
+    }
+
+    /// Flush the buckets whose deadline passed, or wait for the next deadline.
+    fn listen_clock_trigger(&self, interval: Duration) {
+        let mut buckets = self.lock();
+        let now = Instant::now();
+        if !buckets
+            .values()
+            .any(|b| b.deadline.is_some_and(|d| d <= now))
+        {
+            let next = buckets
+                .values()
+                .filter_map(|b| b.deadline)
+                .min()
+                .map_or(interval, |d| d.saturating_duration_since(now));
+            drop(self.opened.wait_timeout(buckets, next));
+            return;
+        }
+        let (expired, rest): (HashMap<_, _>, HashMap<_, _>) = mem::take(&mut *buckets)
+            .into_iter()
+            .partition(|(_, b)| b.deadline.is_some_and(|d| d <= now));
+        *buckets = rest;
+        drop(buckets);
+        for (key, bucket) in expired {
+            (self.consumer)(key, bucket.container);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.lock().values().map(|b| b.len).sum()
+    }
+}
+
+impl<K, E, C> Drop for Partitions<K, E, C>
+where
+    K: Eq + Hash + Send + 'static,
+    E: fmt::Debug + Send + 'static,
+    C: fmt::Debug + Send + 'static,
+{
+    fn drop(&mut self) {
+        self.trigger();
+    }
+}
+
+/// A buffer trigger with an independent buffer per key.
+///
+/// Each key has its own `max_len` and `interval` accounting, and one clock
+/// listener serves all keys. A key holds no state once its buffer is flushed.
+pub struct Keyed<K, E, C>
+where
+    K: Eq + Hash + Send + 'static,
+    E: fmt::Debug + Send + 'static,
+    C: fmt::Debug + Send + 'static,
+{
+    partitions: Outer<Partitions<K, E, C>>,
+}
+
+impl<K, E, C> fmt::Debug for Keyed<K, E, C>
+where
+    K: Eq + Hash + Send + 'static,
+    E: fmt::Debug + Send + 'static,
+    C: fmt::Debug + Send + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.partitions.fmt(f)
+    }
+}
+
+impl<K, E, C> Keyed<K, E, C>
+where
+    K: Eq + Hash + Send + 'static,
+    E: fmt::Debug + Send + 'static,
+    C: fmt::Debug + Send + 'static,
+{
+    /// add elements to the buffer of `key`
+    pub fn push(&self, key: K, value: E) {
+        self.partitions.push(key, value);
+    }
+
+    /// Manual trigger of every key
+    pub fn trigger(&self) {
+        self.partitions.trigger();
+    }
+
+    /// Manual trigger of `key`
+    pub fn trigger_key(&self, key: &K) {
+        self.partitions.trigger_key(key);
+    }
+
+    /// The number of elements of all keys
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.partitions.len()
+    }
+
+    /// is empty
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The number of keys with buffered elements
+    #[must_use]
+    pub fn keys(&self) -> usize {
+        self.partitions.lock().len()
+    }
+}
+
+impl<K, E, C> Drop for Keyed<K, E, C>
+where
+    K: Eq + Hash + Send + 'static,
+    E: fmt::Debug + Send + 'static,
+    C: fmt::Debug + Send + 'static,
+{
+    /// Flush every key, then wake the clock listener so that it lets go of the buffers.
+    fn drop(&mut self) {
+        self.partitions.trigger();
+        let buckets = self.partitions.lock();
+        self.partitions.opened.notify_one();
+        drop(buckets);
+    }
+}
+
+/// keyed buffer trigger builder
+pub struct Builder<K, E, C>
+where
+    K: Eq + Hash + Send + 'static,
+    E: fmt::Debug + Send + 'static,
+    C: fmt::Debug + Send + 'static,
+{
+    name: String,
+    defalut_container: fn() -> C,
+    accumulator: Accumulator<C, E>,
+    consumer: Box<dyn Fn(K, C) + Send + Sync>,
+    max_len: usize,
+    interval: Option<Duration>,
+}
+
+impl<K, E, C> fmt::Debug for Builder<K, E, C>
+where
+    K: Eq + Hash + Send + 'static,
+    E: fmt::Debug + Send + 'static,
+    C: fmt::Debug + Send + 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "name {}", self.name)
+    }
+}
+
+impl<K, E, C> Builder<K, E, C>
+where
+    K: Eq + Hash + Send + 'static,
+    E: fmt::Debug + Send + 'static,
+    C: fmt::Debug + Send + 'static,
+{
+    /// init
+    #[must_use]
+    #[allow(clippy::self_named_constructors)]
+    pub fn builder(defalut_container: fn() -> C) -> Self {
+        Self {
+            name: "anonymous".to_owned(),
+            defalut_container,
+            accumulator: Box::new(|_, _| {}),
+            consumer: Box::new(|_, _| {}),
+            max_len: usize::MAX,
+            interval: None,
+        }
+    }
+
+    /// set `name`
+    #[must_use]
+    pub fn name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// set `accumulator`
+    #[must_use]
+    pub fn accumulator<F>(mut self, accumulator: F) -> Self
+    where
+        F: Fn(&mut C, E) + Send + Sync + 'static,
+    {
+        self.accumulator = Box::new(accumulator);
+        self
+    }
+
+    /// set `consumer`, it receives the key with its container
+    #[must_use]
+    pub fn consumer<F>(mut self, consumer: F) -> Self
+    where
+        F: Fn(K, C) + Send + Sync + 'static,
+    {
+        self.consumer = Box::new(consumer);
+        self
+    }
+
+    /// set `max_len`, per key
+    #[must_use]
+    pub const fn max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// set `interval`, per key
+    #[must_use]
+    pub const fn interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// `build`
+    #[must_use]
+    pub fn build(self) -> Keyed<K, E, C> {
+        let partitions = Partitions {
+            name: self.name,
+            buckets: Mutex::new(HashMap::new()),
+            opened: Condvar::new(),
+            defalut_container: self.defalut_container,
+            accumulator: self.accumulator,
+            consumer: self.consumer,
+            max_len: self.max_len,
+            interval: self.interval,
+        };
+        let partitions = if let Some(interval) = self.interval {
+            lifetime_thread::spawn(partitions, move |inner| {
+                while let Some(p) = inner.get() {
+                    p.listen_clock_trigger(interval);
+                }
+            })
+        } else {
+            lifetime_thread::spawn(partitions, |_| {})
+        };
+        Keyed { partitions }
+    }
+}
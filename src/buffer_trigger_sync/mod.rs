@@ -1,4 +1,7 @@
+use crate::{Error, PushError};
+
 pub(crate) mod general;
+pub(crate) mod keyed;
 pub(crate) mod simple;
 
 /// common trait
@@ -9,15 +12,59 @@ pub trait BufferTrigger<T> {
     /// The number of elements in  `BufferTrigger`
     fn len(&self) -> usize;
 
-    /// add elements
+    /// The number of elements in  `BufferTrigger`, `len` by default
+    ///
+    /// # Errors
+    ///
+    /// `Error::Poisoned` if a panic poisoned the buffer lock.
+    fn try_len(&self) -> Result<usize, Error> {
+        Ok(self.len())
+    }
+
+    /// The total weight of the elements in `BufferTrigger`, 0 without a `weigher`
+    fn weight(&self) -> usize {
+        0
+    }
+
+    /// add elements, the `overflow_policy` decides what happens when the buffer is at `capacity`
+    ///
+    /// Elements pushed after `shutdown` are dropped, `try_push` hands them back.
     fn push(&self, value: T);
 
+    /// add elements, hands the element back if the buffer is at `capacity` or closed,
+    /// `push` by default
+    ///
+    /// # Errors
+    ///
+    /// `PushError::Full` if the buffer is at `capacity` with `Block` or `Reject`,
+    /// `PushError::Closed` once the trigger has been shut down,
+    /// `PushError::Poisoned` if a panic poisoned the buffer lock,
+    /// `PushError::Wal` if the element could not be appended to the write-ahead log.
+    fn try_push(&self, value: T) -> Result<(), PushError<T>> {
+        self.push(value);
+        Ok(())
+    }
+
     /// Manual trigger
     fn trigger(&self);
+
+    /// Manual trigger, reports why the batch was not consumed, `trigger` by default
+    ///
+    /// # Errors
+    ///
+    /// `Error::Poisoned` if a panic poisoned the buffer lock,
+    /// `Error::ConsumerFailed` if the consumer gave up on the batch.
+    fn try_trigger(&self) -> Result<(), Error> {
+        self.trigger();
+        Ok(())
+    }
 }
 
 pub use general::builder::Builder as GeneralBuilder;
 pub use general::General;
 
+pub use keyed::Builder as KeyedBuilder;
+pub use keyed::Keyed;
+
 pub use simple::Builder as SimpleBuilder;
 pub use simple::Simple;
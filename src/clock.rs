@@ -0,0 +1,212 @@
+use crate::timer::{Schedule, SharedTarget, Timer};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex, MutexGuard, PoisonError,
+};
+use std::{
+    fmt, thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// The source of time of a trigger: window starts, deadlines, batch metadata
+/// and the wall-clock alignment of a `TimerMode::Ticker`.
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// The current instant.
+    fn now(&self) -> Instant;
+
+    /// The current wall-clock time.
+    fn system_time(&self) -> SystemTime;
+
+    /// Wait for `dur`, e.g. the backoff between the attempts of a sync consumer.
+    fn sleep(&self, dur: Duration) {
+        thread::sleep(dur);
+    }
+
+    /// Wait for `dur` on a tokio runtime, e.g. the backoff between the attempts
+    /// of an async consumer.
+    fn async_sleep(&self, dur: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        Box::pin(tokio::time::sleep(dur))
+    }
+
+    /// The timer that fires the deadlines of a trigger using this clock,
+    /// `None` to let the trigger wait for them in real time.
+    ///
+    /// A clock that fires them itself implements `Schedule` and hands out a
+    /// `Timer` per trigger, see `ManualClock`.
+    fn timer(&self) -> Option<Timer> {
+        None
+    }
+}
+
+/// The real time, the default `Clock`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A `Clock` that only moves on `advance`, for deterministic tests.
+///
+/// The triggers built with it have no clock listener: `advance` fires their
+/// deadlines in order and returns once they are flushed, sync triggers flush
+/// on the calling thread and async triggers on a runtime of their own. `sleep`
+/// moves the time on without waiting, the deadlines it passes are fired by the
+/// next `advance`. Its wall clock starts at the Unix epoch, so a
+/// `TimerMode::Ticker` ticks at multiples of its `interval`.
+#[derive(Clone)]
+pub struct ManualClock {
+    shared: Arc<Manual>,
+}
+
+struct Manual {
+    state: Mutex<State>,
+    keys: AtomicUsize,
+}
+
+struct State {
+    start: Instant,
+    elapsed: Duration,
+    /// The pending deadlines with their trigger and window.
+    deadlines: Vec<(Instant, usize, u64)>,
+    targets: HashMap<usize, SharedTarget>,
+}
+
+impl fmt::Debug for ManualClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let elapsed = self.shared.lock().elapsed;
+        write!(f, "manual clock at {elapsed:?}")
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ManualClock {
+    /// A clock stopped at the current instant.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Manual {
+                state: Mutex::new(State {
+                    start: Instant::now(),
+                    elapsed: Duration::ZERO,
+                    deadlines: Vec::new(),
+                    targets: HashMap::new(),
+                }),
+                keys: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Move the time on by `by`, firing the deadlines reached on the way.
+    pub fn advance(&self, by: Duration) {
+        let mut state = self.shared.lock();
+        let to = state.start + state.elapsed + by;
+        // one at a time, a flush may schedule the next deadline
+        while let Some(i) = state.next_due(to) {
+            let (deadline, key, window) = state.deadlines.swap_remove(i);
+            state.elapsed = state.elapsed.max(deadline - state.start);
+            let target = state.targets.get(&key).cloned();
+            drop(state);
+            if let Some(target) = target {
+                target
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .expire(window);
+            }
+            state = self.shared.lock();
+        }
+        // a consumer may have slept past `to`
+        state.elapsed = state.elapsed.max(to - state.start);
+        drop(state);
+        // the triggers dropped without closing, checked outside the clock lock
+        let dead: Vec<_> = self
+            .shared
+            .targets()
+            .into_iter()
+            .filter(|(_, t)| !t.lock().unwrap_or_else(PoisonError::into_inner).is_alive())
+            .filter_map(|(key, _)| self.shared.cancel(key))
+            .collect();
+        drop(dead);
+    }
+}
+
+impl State {
+    /// The index of the earliest deadline at or before `to`.
+    fn next_due(&self, to: Instant) -> Option<usize> {
+        self.deadlines
+            .iter()
+            .enumerate()
+            .filter(|(_, (deadline, _, _))| *deadline <= to)
+            .min_by_key(|(_, (deadline, _, _))| *deadline)
+            .map(|(i, _)| i)
+    }
+}
+
+impl Manual {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn targets(&self) -> Vec<(usize, SharedTarget)> {
+        self.lock()
+            .targets
+            .iter()
+            .map(|(key, target)| (*key, target.clone()))
+            .collect()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        let state = self.shared.lock();
+        state.start + state.elapsed
+    }
+
+    fn system_time(&self) -> SystemTime {
+        UNIX_EPOCH + self.shared.lock().elapsed
+    }
+
+    fn sleep(&self, dur: Duration) {
+        self.shared.lock().elapsed += dur;
+    }
+
+    fn async_sleep(&self, dur: Duration) -> Pin<Box<dyn Future<Output = ()> + Send + '_>> {
+        self.sleep(dur);
+        Box::pin(async {})
+    }
+
+    fn timer(&self) -> Option<Timer> {
+        let key = self.shared.keys.fetch_add(1, Ordering::Relaxed);
+        Some(Timer::new(self.shared.clone(), key))
+    }
+}
+
+impl Schedule for Manual {
+    fn register(&self, key: usize, target: SharedTarget) {
+        self.lock().targets.insert(key, target);
+    }
+
+    fn schedule(&self, key: usize, deadline: Instant, window: u64) {
+        self.lock().deadlines.push((deadline, key, window));
+    }
+
+    fn cancel(&self, key: usize) -> Option<SharedTarget> {
+        let mut state = self.lock();
+        state.deadlines.retain(|(_, k, _)| *k != key);
+        state.targets.remove(&key)
+    }
+}
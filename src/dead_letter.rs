@@ -0,0 +1,96 @@
+use crate::ConsumerError;
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+    sync::{Mutex, PoisonError},
+};
+
+/// Receives batches that exhausted their retries.
+///
+/// Implemented for closures `Fn(C, ConsumerError, u32)`, the arguments are
+/// the container, the last error and the number of attempts.
+pub trait DeadLetter<C>: Send + Sync {
+    /// Take a batch the consumer gave up on.
+    fn dead_letter(&self, container: C, error: ConsumerError, attempts: u32);
+}
+
+impl<C, F> DeadLetter<C> for F
+where
+    F: Fn(C, ConsumerError, u32) + Send + Sync,
+{
+    fn dead_letter(&self, container: C, error: ConsumerError, attempts: u32) {
+        self(container, error, attempts);
+    }
+}
+
+/// Appends dead batches to a local file, one batch per line, so they can be
+/// replayed later: `read` decodes them back.
+pub struct FileDeadLetter<C> {
+    file: Mutex<File>,
+    encoder: fn(&C) -> io::Result<Vec<u8>>,
+}
+
+impl<C> fmt::Debug for FileDeadLetter<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileDeadLetter").finish_non_exhaustive()
+    }
+}
+
+impl<C> FileDeadLetter<C> {
+    /// Open `path` for appending, creating it if it does not exist, the
+    /// batches are written with `encoder`.
+    ///
+    /// An encoded batch must not contain a newline.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be opened.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        encoder: fn(&C) -> io::Result<Vec<u8>>,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+            encoder,
+        })
+    }
+
+    /// The batches written to `path`, in order, read back with `decoder`.
+    ///
+    /// Lines the `decoder` rejects, e.g. one torn by a crash, are logged and skipped.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read.
+    pub fn read<P: AsRef<Path>>(
+        path: P,
+        decoder: fn(&[u8]) -> io::Result<C>,
+    ) -> io::Result<Vec<C>> {
+        let path = path.as_ref();
+        let mut batches = Vec::new();
+        for line in BufReader::new(File::open(path)?).split(b'\n') {
+            match decoder(&line?) {
+                Ok(batch) => batches.push(batch),
+                Err(e) => log::error!("skipped a dead letter of {}: {e}", path.display()),
+            }
+        }
+        Ok(batches)
+    }
+}
+
+impl<C> DeadLetter<C> for FileDeadLetter<C> {
+    fn dead_letter(&self, container: C, error: ConsumerError, attempts: u32) {
+        let written = (self.encoder)(&container).and_then(|mut line| {
+            line.push(b'\n');
+            let mut file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+            file.write_all(&line).and_then(|()| file.flush())
+        });
+        match written {
+            Ok(()) => log::warn!("dead letter after {attempts} attempts: {error}"),
+            Err(e) => log::error!("dead letter lost after {attempts} attempts: {error}, {e}"),
+        }
+    }
+}
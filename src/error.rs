@@ -0,0 +1,113 @@
+use std::{any::Any, error, fmt};
+
+/// The error returned by `try_push`, it hands the element back.
+#[derive(PartialEq, Eq)]
+pub enum PushError<E> {
+    /// The buffer has reached its `capacity`.
+    Full(E),
+    /// The trigger has been shut down.
+    Closed(E),
+    /// A panic poisoned the buffer lock.
+    Poisoned(E),
+    /// The element could not be appended to the write-ahead log.
+    Wal(E),
+}
+
+impl<E> PushError<E> {
+    /// The element that was not pushed.
+    pub fn into_inner(self) -> E {
+        match self {
+            Self::Full(e) | Self::Closed(e) | Self::Poisoned(e) | Self::Wal(e) => e,
+        }
+    }
+}
+
+impl<E> fmt::Debug for PushError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => f.write_str("Full(..)"),
+            Self::Closed(_) => f.write_str("Closed(..)"),
+            Self::Poisoned(_) => f.write_str("Poisoned(..)"),
+            Self::Wal(_) => f.write_str("Wal(..)"),
+        }
+    }
+}
+
+impl<E> fmt::Display for PushError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&Error::from(self), f)
+    }
+}
+
+impl<E> error::Error for PushError<E> {}
+
+/// The error of the fallible operations of a trigger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// A panic poisoned the buffer lock.
+    Poisoned,
+    /// The trigger has been shut down.
+    Closed,
+    /// The consumer gave up on a batch, it went to the dead letter if one is set.
+    ConsumerFailed {
+        /// Number of attempts made.
+        attempts: u32,
+        /// The last error of the consumer.
+        reason: String,
+    },
+    /// The buffer has reached its `capacity`.
+    Full,
+    /// The consumer panicked, its batch is lost.
+    ConsumerPanicked {
+        /// The panic message.
+        reason: String,
+    },
+    /// The deadline expired first.
+    Timeout,
+    /// The element could not be appended to the write-ahead log.
+    Wal,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Poisoned => f.write_str("buffer lock is poisoned"),
+            Self::Closed => f.write_str("buffer is closed"),
+            Self::ConsumerFailed { attempts, reason } => {
+                write!(f, "consumer gave up after {attempts} attempts: {reason}")
+            }
+            Self::Full => f.write_str("buffer is full"),
+            Self::ConsumerPanicked { reason } => write!(f, "consumer panicked: {reason}"),
+            Self::Timeout => f.write_str("deadline expired"),
+            Self::Wal => f.write_str("write-ahead log failed"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// The message of a panic payload.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| (*s).to_owned())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "Box<dyn Any>".to_owned())
+}
+
+impl<E> From<&PushError<E>> for Error {
+    fn from(e: &PushError<E>) -> Self {
+        match e {
+            PushError::Full(_) => Self::Full,
+            PushError::Closed(_) => Self::Closed,
+            PushError::Poisoned(_) => Self::Poisoned,
+            PushError::Wal(_) => Self::Wal,
+        }
+    }
+}
+
+impl<E> From<PushError<E>> for Error {
+    fn from(e: PushError<E>) -> Self {
+        Self::from(&e)
+    }
+}
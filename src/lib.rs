@@ -10,6 +10,35 @@
     clippy::nursery,
     clippy::cargo
 )]
+// the duplicate versions are dependencies of `lifetime-thread`
+#![allow(clippy::multiple_crate_versions)]
 
+mod batch;
 pub mod buffer_trigger_async;
 pub mod buffer_trigger_sync;
+mod clock;
+mod dead_letter;
+mod error;
+mod overflow;
+mod resp;
+mod retry;
+mod shutdown;
+mod storage;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod timer;
+mod timer_mode;
+mod wal;
+
+pub use batch::{BatchInfo, FlushReason};
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use dead_letter::{DeadLetter, FileDeadLetter};
+pub use error::{Error, PushError};
+pub use overflow::OverflowPolicy;
+pub use resp::RespStorage;
+pub use retry::{ConsumerError, RetryPolicy};
+pub use shutdown::ShutdownReport;
+pub use storage::{Payload, Storage};
+pub use timer::{Schedule, SharedTarget, Target, Timer, TimerWheel};
+pub use timer_mode::TimerMode;
+pub use wal::Wal;
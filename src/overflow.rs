@@ -0,0 +1,45 @@
+use std::fmt;
+
+/// What `push` and `try_push` do when the buffer is at `capacity`.
+///
+/// `DropOldest` carries the functions that evict the oldest element of the
+/// container and that peek at it, e.g.
+/// `OverflowPolicy::DropOldest(VecDeque::pop_front, VecDeque::front)`. When
+/// nothing can be evicted, because every slot is taken by a batch being
+/// consumed or the `Storage` does not support `evict`, the pushed element is
+/// dropped instead.
+///
+/// Dropped elements are counted and handed to the `on_drop` callback.
+#[derive(Default)]
+pub enum OverflowPolicy<C, E> {
+    /// `push` waits for a free slot, `try_push` hands the element back with `PushError::Full`.
+    #[default]
+    Block,
+    /// `try_push` hands the element back with `PushError::Full`, `push` never
+    /// waits and drops it, as it cannot hand it back.
+    Reject,
+    /// Drop the pushed element.
+    DropNewest,
+    /// Evict the oldest buffered element to make room, a write-ahead log logs
+    /// the element it peeks at before it is evicted.
+    DropOldest(fn(&mut C) -> Option<E>, fn(&C) -> Option<&E>),
+}
+
+impl<C, E> Clone for OverflowPolicy<C, E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<C, E> Copy for OverflowPolicy<C, E> {}
+
+impl<C, E> fmt::Debug for OverflowPolicy<C, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Block => "Block",
+            Self::Reject => "Reject",
+            Self::DropNewest => "DropNewest",
+            Self::DropOldest(..) => "DropOldest",
+        })
+    }
+}
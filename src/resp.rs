@@ -0,0 +1,324 @@
+use crate::Storage;
+use std::convert::TryFrom;
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    mem,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+/// The largest bulk string accepted, as in Redis.
+const MAX_BULK: usize = 512 * 1024 * 1024;
+
+/// A value of the RESP protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Self>>),
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn parse<T: std::str::FromStr>(text: &str) -> io::Result<T> {
+    text.parse().map_err(|_| invalid("invalid length"))
+}
+
+impl Value {
+    /// Read a value, `UnexpectedEof` once the peer has closed the connection.
+    pub fn read<R: BufRead>(reader: &mut R) -> io::Result<Self> {
+        let mut line = Vec::new();
+        reader.read_until(b'\n', &mut line)?;
+        if line.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if !line.ends_with(b"\r\n") {
+            return Err(invalid("line without CRLF"));
+        }
+        line.truncate(line.len() - 2);
+        let (kind, rest) = line.split_first().ok_or_else(|| invalid("empty line"))?;
+        let text = String::from_utf8_lossy(rest).into_owned();
+        match kind {
+            b'+' => Ok(Self::Simple(text)),
+            b'-' => Ok(Self::Error(text)),
+            b':' => parse(&text).map(Self::Integer),
+            b'$' => match parse::<i64>(&text)? {
+                -1 => Ok(Self::Bulk(None)),
+                n => {
+                    let n = usize::try_from(n).map_err(|_| invalid("invalid length"))?;
+                    if n > MAX_BULK {
+                        return Err(invalid("bulk string too large"));
+                    }
+                    let mut data = vec![0; n + 2];
+                    reader.read_exact(&mut data)?;
+                    data.truncate(n);
+                    Ok(Self::Bulk(Some(data)))
+                }
+            },
+            b'*' => match parse::<i64>(&text)? {
+                -1 => Ok(Self::Array(None)),
+                n => (0..n)
+                    .map(|_| Self::read(reader))
+                    .collect::<io::Result<_>>()
+                    .map(|values| Self::Array(Some(values))),
+            },
+            _ => Err(invalid("unknown value type")),
+        }
+    }
+
+    /// Append the encoded value to `buf`.
+    pub fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Self::Simple(s) => buf.extend_from_slice(format!("+{s}\r\n").as_bytes()),
+            Self::Error(s) => buf.extend_from_slice(format!("-{s}\r\n").as_bytes()),
+            Self::Integer(i) => buf.extend_from_slice(format!(":{i}\r\n").as_bytes()),
+            Self::Bulk(None) => buf.extend_from_slice(b"$-1\r\n"),
+            Self::Bulk(Some(data)) => {
+                buf.extend_from_slice(format!("${}\r\n", data.len()).as_bytes());
+                buf.extend_from_slice(data);
+                buf.extend_from_slice(b"\r\n");
+            }
+            Self::Array(None) => buf.extend_from_slice(b"*-1\r\n"),
+            Self::Array(Some(values)) => {
+                buf.extend_from_slice(format!("*{}\r\n", values.len()).as_bytes());
+                for value in values {
+                    value.encode(buf);
+                }
+            }
+        }
+    }
+
+    /// A command, an array of bulk strings.
+    fn command(args: &[&[u8]]) -> Self {
+        Self::Array(Some(
+            args.iter().map(|a| Self::Bulk(Some(a.to_vec()))).collect(),
+        ))
+    }
+
+    /// The value, or the error the server replied with.
+    fn into_result(self) -> io::Result<Self> {
+        match self {
+            Self::Error(e) => Err(io::Error::other(e)),
+            value => Ok(value),
+        }
+    }
+}
+
+/// The length of a list in the reply to `LLEN` or `RPUSH`.
+fn length(reply: Option<&Value>) -> io::Result<usize> {
+    match reply {
+        Some(Value::Integer(n)) => usize::try_from(*n).map_err(|_| invalid("negative length")),
+        _ => Err(invalid("the length is not an integer")),
+    }
+}
+
+/// Keeps the elements in a list of a Redis compatible server, so that several
+/// processes can share one buffer and the buffered elements outlive them.
+///
+/// The batch is taken atomically: the list is renamed to `<key>:processing`,
+/// read, and deleted once decoded, so that a connection broken in between
+/// leaves the batch to be taken again rather than losing it. Elements that
+/// cannot be written, e.g. while the server is down, are kept in memory and
+/// handed with the next batch, so an element may be consumed twice if the
+/// connection broke after the server stored it, or before it deleted a batch.
+/// A trigger does not flush the elements left by a previous process until its
+/// first push or `trigger`.
+///
+/// The I/O is blocking and runs under the lock of the trigger, so this storage
+/// is meant for the sync triggers: with an async `General` every push would
+/// block a thread of the runtime.
+pub struct RespStorage<E> {
+    addrs: Vec<SocketAddr>,
+    key: Vec<u8>,
+    /// The key the list is moved to while its batch is taken.
+    processing: Vec<u8>,
+    timeout: Duration,
+    connection: Mutex<Option<BufReader<TcpStream>>>,
+    /// Elements that could not be written.
+    pending: Vec<E>,
+    /// The length of the list as last replied by the server, so that `len`
+    /// does not query it.
+    remote: usize,
+    encoder: fn(&E) -> io::Result<Vec<u8>>,
+    decoder: fn(&[u8]) -> io::Result<E>,
+}
+
+impl<E> fmt::Debug for RespStorage<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RespStorage")
+            .field("addrs", &self.addrs)
+            .field("key", &String::from_utf8_lossy(&self.key))
+            .field("pending", &self.pending.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E> RespStorage<E> {
+    /// Connect to the server at `addr`, the elements are kept in the list `key`,
+    /// written with `encoder` and read back with `decoder`.
+    ///
+    /// Elements the `encoder` rejects are kept in memory, elements the
+    /// `decoder` rejects are logged and skipped. The length of the list is only
+    /// read here, then taken from the replies to the pushes of this storage.
+    ///
+    /// # Errors
+    ///
+    /// If `addr` cannot be resolved or connected to.
+    pub fn connect<A: ToSocketAddrs>(
+        addr: A,
+        key: &str,
+        encoder: fn(&E) -> io::Result<Vec<u8>>,
+        decoder: fn(&[u8]) -> io::Result<E>,
+    ) -> io::Result<Self> {
+        let mut storage = Self {
+            addrs: addr.to_socket_addrs()?.collect(),
+            key: key.as_bytes().to_vec(),
+            processing: [key.as_bytes(), b":processing"].concat(),
+            timeout: Duration::from_secs(1),
+            connection: Mutex::new(None),
+            pending: Vec::new(),
+            remote: 0,
+            encoder,
+            decoder,
+        };
+        *storage.lock() = Some(storage.open()?);
+        let replies = storage.query(&[&[b"LLEN", &storage.key]])?;
+        storage.remote = length(replies.first())?;
+        Ok(storage)
+    }
+
+    /// set `timeout`, of connecting and of each reply
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        // reconnect with it
+        *self.lock() = None;
+        self
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<BufReader<TcpStream>>> {
+        self.connection
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn open(&self) -> io::Result<BufReader<TcpStream>> {
+        let mut last = io::Error::new(io::ErrorKind::NotFound, "no address");
+        for addr in &self.addrs {
+            match TcpStream::connect_timeout(addr, self.timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    stream.set_nodelay(true)?;
+                    return Ok(BufReader::new(stream));
+                }
+                Err(e) => last = e,
+            }
+        }
+        Err(last)
+    }
+
+    /// Send `commands` at once and read their replies, the connection is
+    /// dropped on error and opened again by the next query.
+    fn query(&self, commands: &[&[&[u8]]]) -> io::Result<Vec<Value>> {
+        let mut connection = self.lock();
+        let stream = match &mut *connection {
+            Some(stream) => stream,
+            none => none.insert(self.open()?),
+        };
+        let mut buf = Vec::new();
+        for command in commands {
+            Value::command(command).encode(&mut buf);
+        }
+        let replies = stream.get_mut().write_all(&buf).and_then(|()| {
+            commands
+                .iter()
+                .map(|_| Value::read(stream).and_then(Value::into_result))
+                .collect()
+        });
+        if replies.is_err() {
+            *connection = None;
+        }
+        replies
+    }
+
+    /// Move the list to the processing key and read it, `take_batch` deletes
+    /// it once decoded. A batch left there by a broken connection is read
+    /// instead, the list then stays for the next batch.
+    fn take_remote(&mut self) -> io::Result<Vec<Vec<u8>>> {
+        let (key, processing) = (&self.key[..], &self.processing[..]);
+        let mut replies = self.query(&[
+            &[b"MULTI"],
+            &[b"RENAMENX", key, processing],
+            &[b"LRANGE", processing, b"0", b"-1"],
+            &[b"LLEN", key],
+            &[b"EXEC"],
+        ])?;
+        match replies.pop() {
+            Some(Value::Array(Some(mut executed))) if executed.len() == 3 => {
+                self.remote = length(executed.get(2))?;
+                match executed.swap_remove(1) {
+                    Value::Array(Some(items)) => items
+                        .into_iter()
+                        .map(|item| match item {
+                            Value::Bulk(Some(data)) => Ok(data),
+                            _ => Err(invalid("LRANGE replied with a non bulk string")),
+                        })
+                        .collect(),
+                    _ => Err(invalid("LRANGE replied with a non array")),
+                }
+            }
+            _ => Err(invalid("EXEC was aborted")),
+        }
+    }
+}
+
+impl<E> Storage<E> for RespStorage<E>
+where
+    E: Send,
+{
+    type Batch = Vec<E>;
+
+    fn push(&mut self, value: E) {
+        let pushed = (self.encoder)(&value)
+            .and_then(|data| self.query(&[&[b"RPUSH", &self.key, &data]]))
+            .and_then(|replies| length(replies.first()));
+        match pushed {
+            // the elements of the other processes sharing the list included
+            Ok(remote) => self.remote = remote,
+            Err(e) => {
+                log::error!("{self:?} RPUSH failed, the element is kept in memory: {e}");
+                self.pending.push(value);
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.remote + self.pending.len()
+    }
+
+    fn take_batch(&mut self) -> Vec<E> {
+        let mut batch = mem::take(&mut self.pending);
+        match self.take_remote() {
+            Ok(items) => {
+                for item in items {
+                    match (self.decoder)(&item) {
+                        Ok(e) => batch.push(e),
+                        Err(e) => log::error!("{self:?} skipped an element: {e}"),
+                    }
+                }
+                if let Err(e) = self.query(&[&[b"DEL", &self.processing]]) {
+                    log::error!("{self:?} DEL failed, the batch will be taken again: {e}");
+                }
+            }
+            Err(e) => log::error!("{self:?} taking the batch failed: {e}"),
+        }
+        batch
+    }
+}
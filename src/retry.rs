@@ -0,0 +1,139 @@
+use std::{
+    collections::hash_map::RandomState,
+    convert::TryFrom,
+    error::Error,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    sync::Arc,
+    time::Duration,
+};
+
+/// The error returned by a fallible consumer.
+pub type ConsumerError = Box<dyn Error + Send + Sync>;
+
+type GiveUp = Arc<dyn Fn(&ConsumerError, u32) + Send + Sync>;
+
+/// How a failed batch is retried by `trigger` and the clock listener.
+///
+/// The delay before attempt `n + 1` is `initial_backoff * multiplier^(n - 1)`,
+/// capped at `max_backoff`. With jitter enabled, a random amount of up to
+/// half the delay is subtracted.
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: u32,
+    jitter: bool,
+    on_give_up: Option<GiveUp>,
+}
+
+impl fmt::Debug for RetryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RetryPolicy")
+            .field("max_attempts", &self.max_attempts)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("multiplier", &self.multiplier)
+            .field("jitter", &self.jitter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for RetryPolicy {
+    /// a single attempt, no retry
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl RetryPolicy {
+    /// init, `max_attempts` includes the first attempt
+    #[must_use]
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2,
+            jitter: true,
+            on_give_up: None,
+        }
+    }
+
+    /// set `initial_backoff` and `max_backoff`
+    #[must_use]
+    pub const fn backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// set `multiplier`
+    #[must_use]
+    pub const fn multiplier(mut self, multiplier: u32) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    /// set `jitter`
+    #[must_use]
+    pub const fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// set the hook called with the last error and the attempt count
+    /// when a batch exhausts its attempts
+    #[must_use]
+    pub fn on_give_up<F>(mut self, on_give_up: F) -> Self
+    where
+        F: Fn(&ConsumerError, u32) + Send + Sync + 'static,
+    {
+        self.on_give_up = Some(Arc::new(on_give_up));
+        self
+    }
+
+    /// `max_attempts`
+    #[must_use]
+    pub const fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// The delay to wait after `attempt` failed, `None` if there are no attempts left.
+    #[must_use]
+    pub fn next_backoff(&self, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let factor = self
+            .multiplier
+            .checked_pow(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        let delay = self
+            .initial_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |d| d.min(self.max_backoff));
+        if self.jitter {
+            Some(delay.saturating_sub(random_below(delay / 2)))
+        } else {
+            Some(delay)
+        }
+    }
+
+    pub(crate) fn give_up(&self, error: &ConsumerError, attempts: u32) {
+        log::error!("consumer gave up after {attempts} attempts: {error}");
+        if let Some(on_give_up) = &self.on_give_up {
+            on_give_up(error, attempts);
+        }
+    }
+}
+
+/// A random duration in `[0, bound)`
+fn random_below(bound: Duration) -> Duration {
+    let nanos = u64::try_from(bound.as_nanos()).unwrap_or(u64::MAX);
+    if nanos == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_nanos(RandomState::new().build_hasher().finish() % nanos)
+}
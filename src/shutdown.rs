@@ -0,0 +1,34 @@
+use crate::Error;
+
+/// What `shutdown` flushed and what it left behind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Number of elements consumed by the shutdown.
+    pub flushed: usize,
+    /// Number of elements that were not consumed before the deadline, or whose
+    /// consumer failed.
+    pub left: usize,
+    /// Whether the clock listener stopped before the deadline.
+    pub listener_stopped: bool,
+}
+
+impl ShutdownReport {
+    /// Everything was flushed and the listener stopped in time.
+    #[must_use]
+    pub const fn is_complete(&self) -> bool {
+        self.left == 0 && self.listener_stopped
+    }
+
+    /// The number of flushed elements, `Error::Timeout` unless the shutdown is complete.
+    ///
+    /// # Errors
+    ///
+    /// `Error::Timeout` if elements were left or the listener did not stop in time.
+    pub const fn into_result(self) -> Result<usize, Error> {
+        if self.is_complete() {
+            Ok(self.flushed)
+        } else {
+            Err(Error::Timeout)
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{fmt, mem};
+
+/// accumulator function
+pub type Accumulator<C, E> = Box<dyn Fn(&mut C, E) + Send + Sync>;
+
+/// Where a `General` trigger keeps the elements of the current batch.
+///
+/// The trigger calls it under its buffer lock, so an implementation does not
+/// need to synchronize itself.
+pub trait Storage<E>: Send {
+    /// The container handed to the consumer.
+    type Batch;
+
+    /// Add `value` to the current batch.
+    fn push(&mut self, value: E);
+
+    /// Number of elements in the current batch.
+    fn len(&self) -> usize;
+
+    /// Take the current batch out, the storage starts an empty one.
+    fn take_batch(&mut self) -> Self::Batch;
+
+    /// Whether the current batch is empty.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Evict an element of the current batch with the function of an
+    /// `OverflowPolicy`, `None` if nothing can be evicted.
+    fn evict(&mut self, evict: fn(&mut Self::Batch) -> Option<E>) -> Option<E> {
+        let _ = evict;
+        None
+    }
+
+    /// The element `evict` would evict, with the function of an `OverflowPolicy`.
+    ///
+    /// A trigger with a write-ahead log only evicts the elements it can peek at.
+    fn peek(&self, peek: fn(&Self::Batch) -> Option<&E>) -> Option<&E> {
+        let _ = peek;
+        None
+    }
+}
+
+/// A container kept in memory, filled by an `accumulator`.
+pub struct Payload<E, C> {
+    len: usize,
+    container: C,
+    defalut_container: fn() -> C,
+    pub(crate) accumulator: Accumulator<C, E>,
+}
+
+impl<E, C> fmt::Debug for Payload<E, C>
+where
+    C: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Payload")
+            .field("len", &self.len)
+            .field("container", &self.container)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<E, C> Payload<E, C> {
+    /// An empty container, `accumulator` adds the elements to it.
+    #[must_use]
+    pub fn new<F>(defalut_container: fn() -> C, accumulator: F) -> Self
+    where
+        F: Fn(&mut C, E) + Send + Sync + 'static,
+    {
+        Self {
+            len: 0,
+            container: defalut_container(),
+            defalut_container,
+            accumulator: Box::new(accumulator),
+        }
+    }
+}
+
+impl<E, C> Storage<E> for Payload<E, C>
+where
+    C: Send,
+{
+    type Batch = C;
+
+    fn push(&mut self, value: E) {
+        (self.accumulator)(&mut self.container, value);
+        self.len += 1;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn take_batch(&mut self) -> C {
+        self.len = 0;
+        mem::replace(&mut self.container, (self.defalut_container)())
+    }
+
+    fn evict(&mut self, evict: fn(&mut C) -> Option<E>) -> Option<E> {
+        let evicted = evict(&mut self.container);
+        if evicted.is_some() {
+            self.len -= 1;
+        }
+        evicted
+    }
+
+    fn peek(&self, peek: fn(&C) -> Option<&E>) -> Option<&E> {
+        peek(&self.container)
+    }
+}
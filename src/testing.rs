@@ -0,0 +1,433 @@
+//! Helpers to test code built on the triggers, enabled by the `testing` feature.
+//!
+//! A `Recorder` hands out consumers for the sync and async `Simple` builders
+//! and keeps every batch they receive, with its `BatchInfo`:
+//!
+//! ```
+//! use buffer_trigger::buffer_trigger_sync::{BufferTrigger, SimpleBuilder};
+//! use buffer_trigger::testing::Recorder;
+//! use buffer_trigger::FlushReason;
+//! use std::time::Duration;
+//!
+//! let recorder = Recorder::new();
+//! let trigger = SimpleBuilder::builder(Vec::default)
+//!     .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+//!     .consumer_with_info(recorder.consumer())
+//!     .max_len(2)
+//!     .build();
+//! trigger.push(1);
+//! trigger.push(2);
+//! assert!(recorder.wait_for_batches(1, Duration::from_secs(1)));
+//! recorder.assert_sizes(&[2]);
+//! recorder.assert_reasons(&[FlushReason::MaxLen]);
+//! ```
+//!
+//! A `RespServer` stands in for the Redis server of a `RespStorage`.
+use crate::resp::Value;
+use crate::{BatchInfo, FlushReason};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::{
+    fmt,
+    future::{self, Ready},
+    io::{self, BufReader, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::{sync::Notify, time::timeout};
+
+/// A batch received by a `Recorder`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recorded<C> {
+    /// The container handed to the consumer.
+    pub container: C,
+    /// The metadata of the batch.
+    pub info: BatchInfo,
+}
+
+struct Shared<C> {
+    batches: Mutex<Vec<Recorded<C>>>,
+    recorded: Condvar,
+    notify: Notify,
+}
+
+/// Records the batches of the consumers it hands out, clones share the record.
+pub struct Recorder<C> {
+    shared: Arc<Shared<C>>,
+}
+
+impl<C> Clone for Recorder<C> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<C> fmt::Debug for Recorder<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "recorder of {} batches", self.len())
+    }
+}
+
+impl<C> Default for Recorder<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> Recorder<C> {
+    /// An empty record.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                batches: Mutex::new(Vec::new()),
+                recorded: Condvar::new(),
+                notify: Notify::new(),
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Recorded<C>>> {
+        self.shared
+            .batches
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn record(&self, container: C, info: BatchInfo) {
+        self.lock().push(Recorded { container, info });
+        self.shared.recorded.notify_all();
+        self.shared.notify.notify_waiters();
+    }
+
+    /// Number of batches received so far.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether no batch has been received yet.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Take the batches received so far, the record starts over.
+    #[must_use]
+    pub fn take(&self) -> Vec<Recorded<C>> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    /// The metadata of the batches received so far.
+    #[must_use]
+    pub fn infos(&self) -> Vec<BatchInfo> {
+        self.lock().iter().map(|r| r.info.clone()).collect()
+    }
+
+    /// The number of elements of each batch received so far.
+    #[must_use]
+    pub fn sizes(&self) -> Vec<usize> {
+        self.lock().iter().map(|r| r.info.len).collect()
+    }
+
+    /// Why each batch received so far was flushed.
+    #[must_use]
+    pub fn reasons(&self) -> Vec<FlushReason> {
+        self.lock().iter().map(|r| r.info.reason).collect()
+    }
+
+    /// Block until `n` batches have been received, false if `timeout` elapsed first.
+    ///
+    /// Do not call it from an async trigger's runtime, use `wait_for_batches_async`.
+    #[must_use]
+    pub fn wait_for_batches(&self, n: usize, timeout: Duration) -> bool {
+        let (batches, _) = self
+            .shared
+            .recorded
+            .wait_timeout_while(self.lock(), timeout, |batches| batches.len() < n)
+            .unwrap_or_else(PoisonError::into_inner);
+        batches.len() >= n
+    }
+
+    /// Wait until `n` batches have been received, false if `timeout` elapsed first.
+    pub async fn wait_for_batches_async(&self, n: usize, wait: Duration) -> bool {
+        let deadline = Instant::now() + wait;
+        loop {
+            // registered before the check, so that no batch is missed
+            let recorded = self.shared.notify.notified();
+            if self.len() >= n {
+                return true;
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if timeout(left, recorded).await.is_err() {
+                return self.len() >= n;
+            }
+        }
+    }
+
+    /// Assert the number of elements of each batch received so far.
+    ///
+    /// # Panics
+    ///
+    /// If the sizes differ from `expected`.
+    #[track_caller]
+    pub fn assert_sizes(&self, expected: &[usize]) {
+        assert_eq!(self.sizes(), expected, "sizes of the recorded batches");
+    }
+
+    /// Assert why each batch received so far was flushed.
+    ///
+    /// # Panics
+    ///
+    /// If the reasons differ from `expected`.
+    #[track_caller]
+    pub fn assert_reasons(&self, expected: &[FlushReason]) {
+        assert_eq!(self.reasons(), expected, "reasons of the recorded batches");
+    }
+}
+
+impl<C> Recorder<C>
+where
+    C: Send + 'static,
+{
+    /// A consumer for the sync `consumer_with_info`.
+    pub fn consumer(&self) -> impl Fn(C, &BatchInfo) + Send + Sync + 'static {
+        let recorder = self.clone();
+        move |container, info| recorder.record(container, info.clone())
+    }
+
+    /// A consumer for the async `async_consumer_with_info`.
+    pub fn async_consumer(&self) -> impl Fn(C, BatchInfo) -> Ready<()> + Send + Sync + 'static {
+        let recorder = self.clone();
+        move |container, info| {
+            recorder.record(container, info);
+            future::ready(())
+        }
+    }
+}
+
+impl<C> Recorder<C>
+where
+    C: Clone,
+{
+    /// The containers received so far.
+    #[must_use]
+    pub fn batches(&self) -> Vec<C> {
+        self.lock().iter().map(|r| r.container.clone()).collect()
+    }
+}
+
+type Lists = HashMap<Vec<u8>, Vec<Vec<u8>>>;
+
+/// An in-process server speaking the part of RESP used by `RespStorage`:
+/// `PING`, `RPUSH`, `LLEN`, `LRANGE`, `DEL`, `MULTI` and `EXEC`.
+///
+/// It stops accepting connections once dropped.
+pub struct RespServer {
+    addr: SocketAddr,
+    lists: Arc<Mutex<Lists>>,
+    connections: Arc<Mutex<Vec<TcpStream>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl fmt::Debug for RespServer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "resp server on {}", self.addr)
+    }
+}
+
+impl RespServer {
+    /// Listen on a free port of the loopback interface.
+    ///
+    /// # Errors
+    ///
+    /// If no port can be bound.
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let server = Self {
+            addr: listener.local_addr()?,
+            lists: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(Vec::new())),
+            stopped: Arc::new(AtomicBool::new(false)),
+        };
+        let (lists, connections, stopped) = (
+            server.lists.clone(),
+            server.connections.clone(),
+            server.stopped.clone(),
+        );
+        thread::Builder::new()
+            .name("buffer-trigger-resp-server".to_owned())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::Acquire) {
+                        break;
+                    }
+                    let Ok(stream) = stream else { continue };
+                    if let Ok(clone) = stream.try_clone() {
+                        lock(&connections).push(clone);
+                    }
+                    let lists = lists.clone();
+                    let _ = thread::Builder::new()
+                        .name("buffer-trigger-resp-connection".to_owned())
+                        .spawn(move || serve(stream, &lists));
+                }
+            })?;
+        Ok(server)
+    }
+
+    /// The address to connect to.
+    #[must_use]
+    pub const fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The elements of the list `key`.
+    #[must_use]
+    pub fn list(&self, key: &str) -> Vec<Vec<u8>> {
+        lock(&self.lists)
+            .get(key.as_bytes())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Close every open connection, as a restarted server would.
+    pub fn disconnect(&self) {
+        for stream in lock(&self.connections).drain(..) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+impl Drop for RespServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+        self.disconnect();
+        // wake the accepting thread so that it sees `stopped`
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Answer the commands of a connection until it is closed.
+fn serve(stream: TcpStream, lists: &Mutex<Lists>) {
+    let mut reader = BufReader::new(stream);
+    // the commands queued by `MULTI`
+    let mut queued = None;
+    while let Ok(value) = Value::read(&mut reader) {
+        let reply = command(value).map_or_else(
+            || Value::Error("ERR protocol error".to_owned()),
+            |args| reply(args, &mut queued, lists),
+        );
+        let mut buf = Vec::new();
+        reply.encode(&mut buf);
+        if reader.get_mut().write_all(&buf).is_err() {
+            break;
+        }
+    }
+}
+
+/// Queue the command within a transaction, run it otherwise.
+fn reply(
+    args: Vec<Vec<u8>>,
+    queued: &mut Option<Vec<Vec<Vec<u8>>>>,
+    lists: &Mutex<Lists>,
+) -> Value {
+    match (args[0].to_ascii_uppercase().as_slice(), &mut *queued) {
+        (b"MULTI", None) => {
+            *queued = Some(Vec::new());
+            Value::Simple("OK".to_owned())
+        }
+        (b"EXEC", Some(_)) => {
+            let mut lists = lock(lists);
+            let replies = queued
+                .take()
+                .into_iter()
+                .flatten()
+                .map(|args| execute(&mut lists, &args))
+                .collect();
+            drop(lists);
+            Value::Array(Some(replies))
+        }
+        (b"MULTI" | b"EXEC", _) => Value::Error("ERR MULTI/EXEC misuse".to_owned()),
+        (_, Some(commands)) => {
+            commands.push(args);
+            Value::Simple("QUEUED".to_owned())
+        }
+        (_, None) => execute(&mut lock(lists), &args),
+    }
+}
+
+/// The arguments of a command, an array of bulk strings.
+fn command(value: Value) -> Option<Vec<Vec<u8>>> {
+    match value {
+        Value::Array(Some(values)) if !values.is_empty() => values
+            .into_iter()
+            .map(|v| match v {
+                Value::Bulk(Some(arg)) => Some(arg),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
+
+fn integer(n: usize) -> Value {
+    Value::Integer(i64::try_from(n).unwrap_or(i64::MAX))
+}
+
+/// The index of a possibly negative `LRANGE` bound in a list of `len`.
+fn index(arg: &[u8], len: usize) -> Option<usize> {
+    let i: i64 = std::str::from_utf8(arg).ok()?.parse().ok()?;
+    let len = i64::try_from(len).ok()?;
+    usize::try_from(if i < 0 { (len + i).max(0) } else { i }).ok()
+}
+
+fn execute(lists: &mut Lists, args: &[Vec<u8>]) -> Value {
+    match (args[0].to_ascii_uppercase().as_slice(), &args[1..]) {
+        (b"PING", []) => Value::Simple("PONG".to_owned()),
+        (b"RPUSH", [key, values @ ..]) if !values.is_empty() => {
+            let list = lists.entry(key.clone()).or_default();
+            list.extend(values.iter().cloned());
+            integer(list.len())
+        }
+        (b"LLEN", [key]) => integer(lists.get(key).map_or(0, Vec::len)),
+        (b"LRANGE", [key, start, stop]) => {
+            let list = lists.get(key).map_or(&[][..], |list| &list[..]);
+            match (index(start, list.len()), index(stop, list.len())) {
+                (Some(start), Some(stop)) => Value::Array(Some(
+                    list.iter()
+                        .take(stop.saturating_add(1))
+                        .skip(start)
+                        .map(|item| Value::Bulk(Some(item.clone())))
+                        .collect(),
+                )),
+                _ => Value::Error("ERR value is not an integer or out of range".to_owned()),
+            }
+        }
+        (b"RENAMENX", [key, new]) => match (lists.contains_key(key), lists.contains_key(new)) {
+            (false, _) => Value::Error("ERR no such key".to_owned()),
+            (true, true) => integer(0),
+            (true, false) => {
+                let list = lists.remove(key).unwrap_or_default();
+                lists.insert(new.clone(), list);
+                integer(1)
+            }
+        },
+        (b"DEL", keys) if !keys.is_empty() => integer(
+            keys.iter()
+                .filter(|key| lists.remove(*key).is_some())
+                .count(),
+        ),
+        _ => Value::Error("ERR unknown command".to_owned()),
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc::{self, Receiver, SendError, Sender},
+    Arc, Condvar, Mutex, MutexGuard, PoisonError, TryLockError, Weak,
+};
+use std::{
+    fmt, mem,
+    panic::{self, AssertUnwindSafe},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Slots per level, a power of two.
+const SLOTS: usize = 64;
+const SLOT_BITS: u32 = 6;
+const LEVELS: u32 = 4;
+/// How often an idle wheel checks whether it and its triggers are still in use.
+const IDLE: Duration = Duration::from_secs(1);
+/// Flush workers of a `TimerWheel` built with `new`.
+const WORKERS: usize = 4;
+
+/// The slot of the tick `at` in the level `level`.
+#[allow(clippy::cast_possible_truncation)]
+const fn slot(at: u64, level: u32) -> usize {
+    // masked to the number of slots, so it fits
+    ((at >> (level * SLOT_BITS)) & (SLOTS as u64 - 1)) as usize
+}
+
+/// A trigger registered with a `TimerWheel` or the `Timer` of a `Clock`.
+pub trait Target: Send {
+    /// Flush the window `window` if it is still open, it returns once the
+    /// consumer is done.
+    fn expire(&self, window: u64);
+    /// Flush the window `window` like `expire`, called on a flush worker of a
+    /// `TimerWheel`, an async trigger hands the flush over to its runtime.
+    fn fire(&self, window: u64);
+    /// False once the trigger has been dropped.
+    fn is_alive(&self) -> bool;
+}
+
+/// A target shared with the thread that fires it.
+pub type SharedTarget = Arc<Mutex<Box<dyn Target>>>;
+
+/// A deadline of the window `window` of the trigger `key`.
+struct Entry {
+    at: u64,
+    key: usize,
+    window: u64,
+}
+
+/// Hierarchical wheel of `LEVELS` levels, each slot of a level spans a whole lower level.
+struct Wheel {
+    start: Instant,
+    tick: Duration,
+    /// The current tick, every entry of an earlier tick has expired.
+    now: u64,
+    levels: Vec<Vec<Vec<Entry>>>,
+    pending: usize,
+    targets: HashMap<usize, SharedTarget>,
+    last_sweep: Instant,
+}
+
+impl Wheel {
+    /// The tick of `instant`, rounded down.
+    fn tick_of(&self, instant: Instant) -> u64 {
+        let ticks = instant.saturating_duration_since(self.start).as_nanos() / self.tick.as_nanos();
+        u64::try_from(ticks).unwrap_or(u64::MAX)
+    }
+
+    fn until_next_tick(&self) -> Duration {
+        let tick = self.tick.as_nanos();
+        let left = tick - self.start.elapsed().as_nanos() % tick;
+        Duration::from_nanos(u64::try_from(left).unwrap_or(u64::MAX))
+    }
+
+    fn insert(&mut self, entry: Entry) {
+        // the level is given by the highest bit where `at` and `now` differ
+        let level = if entry.at <= self.now {
+            0
+        } else {
+            let bit = u64::BITS - 1 - (entry.at ^ self.now).leading_zeros();
+            (bit / SLOT_BITS).min(LEVELS - 1)
+        };
+        self.levels[level as usize][slot(entry.at, level)].push(entry);
+    }
+
+    /// Move to the tick `to`, collecting the expired entries.
+    fn advance(&mut self, to: u64, expired: &mut Vec<Entry>) {
+        while self.now < to {
+            if self.pending == 0 {
+                self.now = to;
+                break;
+            }
+            self.now += 1;
+            // cascade the slots that start at this tick, from the top level down
+            for level in (1..LEVELS).rev() {
+                if self.now & ((1 << (level * SLOT_BITS)) - 1) == 0 {
+                    let slot = slot(self.now, level);
+                    for entry in mem::take(&mut self.levels[level as usize][slot]) {
+                        self.insert(entry);
+                    }
+                }
+            }
+            let due = mem::take(&mut self.levels[0][slot(self.now, 0)]);
+            self.pending -= due.len();
+            expired.extend(due);
+        }
+    }
+
+    /// Take the targets whose trigger has been dropped, a target being fired is alive.
+    fn sweep(&mut self) -> Vec<SharedTarget> {
+        self.last_sweep = Instant::now();
+        let dead: Vec<usize> = self
+            .targets
+            .iter()
+            .filter(|(_, t)| match t.try_lock() {
+                Ok(t) => !t.is_alive(),
+                Err(TryLockError::Poisoned(e)) => !e.into_inner().is_alive(),
+                Err(TryLockError::WouldBlock) => false,
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        dead.iter()
+            .filter_map(|key| self.targets.remove(key))
+            .collect()
+    }
+}
+
+struct Shared {
+    wheel: Mutex<Wheel>,
+    scheduled: Condvar,
+    keys: AtomicUsize,
+}
+
+/// A timer wheel shared by triggers, so that their clock flushes run on a
+/// single thread instead of a listener per trigger.
+///
+/// The wheel thread only fires the deadlines: sync triggers flush on a bounded
+/// pool of workers of the wheel, so that a slow consumer does not delay the
+/// other triggers. Async triggers flush on the runtime they were built on.
+#[derive(Clone)]
+pub struct TimerWheel {
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for TimerWheel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let wheel = self
+            .shared
+            .wheel
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let (tick, triggers, pending) = (wheel.tick, wheel.targets.len(), wheel.pending);
+        drop(wheel);
+        write!(f, "tick {tick:?} triggers {triggers} pending {pending}")
+    }
+}
+
+impl Default for TimerWheel {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(10))
+    }
+}
+
+impl TimerWheel {
+    /// A wheel with a resolution of `tick`, deadlines are rounded up to it.
+    ///
+    /// Its thread stops once the wheel and the triggers using it are dropped.
+    #[must_use]
+    pub fn new(tick: Duration) -> Self {
+        Self::with_workers(tick, WORKERS)
+    }
+
+    /// A wheel with a resolution of `tick` and `workers` threads to flush the
+    /// sync triggers, at least one.
+    ///
+    /// Its threads stop once the wheel and the triggers using it are dropped.
+    #[must_use]
+    pub fn with_workers(tick: Duration, workers: usize) -> Self {
+        let now = Instant::now();
+        let shared = Arc::new(Shared {
+            wheel: Mutex::new(Wheel {
+                start: now,
+                tick: tick.max(Duration::from_millis(1)),
+                now: 0,
+                levels: (0..LEVELS)
+                    .map(|_| (0..SLOTS).map(|_| Vec::new()).collect())
+                    .collect(),
+                pending: 0,
+                targets: HashMap::new(),
+                last_sweep: now,
+            }),
+            scheduled: Condvar::new(),
+            keys: AtomicUsize::new(0),
+        });
+        let (sender, jobs) = mpsc::channel();
+        let jobs = Arc::new(Mutex::new(jobs));
+        for _ in 0..workers.max(1) {
+            let jobs = jobs.clone();
+            let spawned = thread::Builder::new()
+                .name("buffer-trigger-flush".to_owned())
+                .spawn(move || work(&jobs));
+            if let Err(e) = spawned {
+                log::error!("timer wheel: cannot start a flush worker: {e}");
+            }
+        }
+        drop(jobs);
+        let weak = Arc::downgrade(&shared);
+        let _ = thread::Builder::new()
+            .name("buffer-trigger-timer".to_owned())
+            .spawn(move || run(&weak, &sender));
+        Self { shared }
+    }
+
+    /// A handle for a new trigger.
+    pub(crate) fn timer(&self) -> Timer {
+        let key = self.shared.keys.fetch_add(1, Ordering::Relaxed);
+        Timer::new(self.shared.clone(), key)
+    }
+}
+
+/// Work handed by the wheel thread to its flush workers.
+enum Job {
+    /// Fire the window of a trigger.
+    Fire(SharedTarget, u64),
+    /// Drop the triggers that are no longer in use, their last flush runs in `drop`.
+    Drop(Vec<SharedTarget>),
+}
+
+impl Job {
+    fn run(self) {
+        match self {
+            Self::Fire(target, window) => {
+                let fired = panic::catch_unwind(AssertUnwindSafe(|| {
+                    target
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .fire(window);
+                }));
+                if fired.is_err() {
+                    log::error!("timer wheel: window {window} lost to a panic");
+                }
+            }
+            Self::Drop(dead) => {
+                if panic::catch_unwind(AssertUnwindSafe(|| drop(dead))).is_err() {
+                    log::error!("timer wheel: a dropped trigger panicked");
+                }
+            }
+        }
+    }
+}
+
+/// Run the jobs of the wheel until it stops.
+fn work(jobs: &Mutex<Receiver<Job>>) {
+    loop {
+        let job = jobs.lock().unwrap_or_else(PoisonError::into_inner).recv();
+        match job {
+            Ok(job) => job.run(),
+            Err(_) => break,
+        }
+    }
+}
+
+/// Fire the expired deadlines until the wheel is dropped.
+///
+/// The jobs run on the calling thread if no flush worker could be started.
+fn run(shared: &Weak<Shared>, workers: &Sender<Job>) {
+    let submit = |job| {
+        if let Err(SendError(job)) = workers.send(job) {
+            Job::run(job);
+        }
+    };
+    while let Some(shared) = shared.upgrade() {
+        let mut wheel = shared.wheel.lock().unwrap_or_else(PoisonError::into_inner);
+        let now = wheel.tick_of(Instant::now());
+        let mut expired = Vec::new();
+        wheel.advance(now, &mut expired);
+        let fire: Vec<_> = expired
+            .into_iter()
+            .filter_map(|e| wheel.targets.get(&e.key).map(|t| (t.clone(), e.window)))
+            .collect();
+        let dead = if wheel.last_sweep.elapsed() >= IDLE {
+            wheel.sweep()
+        } else {
+            Vec::new()
+        };
+        drop(wheel);
+        // triggers flush without the wheel lock, they schedule their next deadline
+        for (target, window) in fire {
+            submit(Job::Fire(target, window));
+        }
+        if !dead.is_empty() {
+            submit(Job::Drop(dead));
+        }
+        let wheel = shared.wheel.lock().unwrap_or_else(PoisonError::into_inner);
+        let wait = if wheel.pending == 0 {
+            IDLE
+        } else {
+            wheel.until_next_tick()
+        };
+        drop(
+            shared
+                .scheduled
+                .wait_timeout(wheel, wait)
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+    }
+}
+
+impl Schedule for Shared {
+    fn register(&self, key: usize, target: SharedTarget) {
+        self.lock().targets.insert(key, target);
+    }
+
+    fn schedule(&self, key: usize, deadline: Instant, window: u64) {
+        let mut wheel = self.lock();
+        // round up, and never into a tick that has already been fired
+        let at = wheel
+            .tick_of(deadline + wheel.tick.saturating_sub(Duration::from_nanos(1)))
+            .max(wheel.now + 1);
+        let idle = wheel.pending == 0;
+        wheel.pending += 1;
+        wheel.insert(Entry { at, key, window });
+        drop(wheel);
+        if idle {
+            self.scheduled.notify_one();
+        }
+    }
+
+    fn cancel(&self, key: usize) -> Option<SharedTarget> {
+        self.lock().targets.remove(&key)
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Wheel> {
+        self.wheel.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Fires the deadlines of the triggers registered with it.
+pub trait Schedule: Send + Sync {
+    /// Add the trigger `key`.
+    fn register(&self, key: usize, target: SharedTarget);
+    /// Expire the window `window` of the trigger `key` at `deadline`.
+    fn schedule(&self, key: usize, deadline: Instant, window: u64);
+    /// Remove the trigger `key`, it is handed back to be dropped outside of any lock.
+    fn cancel(&self, key: usize) -> Option<SharedTarget>;
+}
+
+/// The registration of a trigger with a `TimerWheel` or a `ManualClock`.
+#[derive(Clone)]
+pub struct Timer {
+    scheduler: Arc<dyn Schedule>,
+    key: usize,
+}
+
+impl fmt::Debug for Timer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "timer {}", self.key)
+    }
+}
+
+impl Timer {
+    /// The trigger `key` of `scheduler`, the keys of a scheduler are distinct.
+    #[must_use]
+    pub fn new(scheduler: Arc<dyn Schedule>, key: usize) -> Self {
+        Self { scheduler, key }
+    }
+
+    /// Hand the trigger over, before it schedules any deadline.
+    pub fn register<T>(&self, target: T)
+    where
+        T: Target + 'static,
+    {
+        let target: Box<dyn Target> = Box::new(target);
+        self.scheduler
+            .register(self.key, Arc::new(Mutex::new(target)));
+    }
+
+    /// Expire the window `window` at `deadline`.
+    pub fn schedule(&self, deadline: Instant, window: u64) {
+        self.scheduler.schedule(self.key, deadline, window);
+    }
+
+    /// Stop firing for this trigger, its pending deadlines are ignored.
+    pub fn cancel(&self) {
+        drop(self.scheduler.cancel(self.key));
+    }
+}
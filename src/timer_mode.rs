@@ -0,0 +1,64 @@
+use crate::Clock;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+/// How the `interval` of a window is measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TimerMode {
+    /// Flush `interval` after the first element of the batch was pushed.
+    #[default]
+    MaxAge,
+    /// Flush once no element was pushed for `interval`, e.g. to batch UI or file events.
+    Debounce {
+        /// Flush at the latest this long after the first element, so that
+        /// constant traffic still flushes.
+        max_wait: Option<Duration>,
+    },
+    /// Flush at every multiple of `interval` of the wall clock, e.g. at :00,
+    /// :10, :20 for 10 seconds, regardless of when the first element was pushed.
+    Ticker {
+        /// Flush an empty batch too when nothing was pushed.
+        heartbeat: bool,
+    },
+}
+
+impl TimerMode {
+    /// The deadline of a window opened at `first_push_at`, after a push at `now`,
+    /// `None` for a `Ticker` which has no windows.
+    pub(crate) fn deadline(
+        self,
+        first_push_at: Instant,
+        now: Instant,
+        interval: Duration,
+    ) -> Option<Instant> {
+        match self {
+            Self::MaxAge => Some(first_push_at + interval),
+            Self::Debounce { max_wait } => {
+                let quiet = now + interval;
+                Some(max_wait.map_or(quiet, |max_wait| quiet.min(first_push_at + max_wait)))
+            }
+            Self::Ticker { .. } => None,
+        }
+    }
+
+    /// Whether a `Ticker` flushes empty batches, `None` for the other modes.
+    pub(crate) const fn ticker(self) -> Option<bool> {
+        match self {
+            Self::Ticker { heartbeat } => Some(heartbeat),
+            _ => None,
+        }
+    }
+}
+
+/// The next multiple of `interval` of the wall clock of `clock`.
+pub fn next_tick(clock: &dyn Clock, interval: Duration) -> Instant {
+    let now = clock.now();
+    let since_epoch = clock
+        .system_time()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let interval = interval.as_nanos().max(1);
+    let left = interval - since_epoch % interval;
+    now + Duration::from_nanos(u64::try_from(left).unwrap_or(u64::MAX))
+}
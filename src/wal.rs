@@ -0,0 +1,254 @@
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    mem,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+/// Extension of the segment files.
+const EXTENSION: &str = "wal";
+/// Tag of the line of a pushed element.
+const PUSHED: u8 = b'+';
+/// Tag of the line of an element evicted by `OverflowPolicy::DropOldest`.
+const EVICTED: u8 = b'-';
+
+/// A write-ahead log of the buffered elements, a directory of segments.
+///
+/// Each batch has its own segment, one element per line, removed once the
+/// consumer succeeds on the batch or the dead letter took it. A failed or
+/// panicking batch stays on disk: `build` replays what a previous process left
+/// in the directory as the first batch, with `FlushReason::Replay`. The
+/// elements evicted by `OverflowPolicy::DropOldest` are logged too, so that
+/// they are not replayed. Only one trigger may use a directory.
+///
+/// An async trigger runs the file I/O on the blocking pool of the runtime, an
+/// element whose `push` is cancelled while it is being logged may be replayed.
+pub struct Wal<E> {
+    dir: PathBuf,
+    /// The segment of batch 0, the ones below were left by a previous process.
+    base: u64,
+    /// The segment being appended to.
+    current: Mutex<Option<(u64, File)>>,
+    /// The segments replayed with batch 0.
+    recovered: Mutex<Vec<PathBuf>>,
+    encoder: fn(&E) -> io::Result<Vec<u8>>,
+    decoder: fn(&[u8]) -> io::Result<E>,
+}
+
+impl<E> fmt::Debug for Wal<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Wal")
+            .field("dir", &self.dir)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "wal")]
+impl<E> Wal<E>
+where
+    E: serde::Serialize + serde::de::DeserializeOwned,
+{
+    /// Open the log in `dir`, creating it if it does not exist, the elements
+    /// are written as JSON.
+    ///
+    /// # Errors
+    ///
+    /// If the directory cannot be created or read.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        Self::with_codec(
+            dir,
+            |e| serde_json::to_vec(e).map_err(io::Error::from),
+            |line| serde_json::from_slice(line).map_err(io::Error::from),
+        )
+    }
+}
+
+impl<E> Wal<E> {
+    /// Open the log in `dir`, creating it if it does not exist, the elements
+    /// are written with `encoder` and read back with `decoder`.
+    ///
+    /// An encoded element must not contain a newline.
+    ///
+    /// # Errors
+    ///
+    /// If the directory cannot be created or read.
+    pub fn with_codec<P: AsRef<Path>>(
+        dir: P,
+        encoder: fn(&E) -> io::Result<Vec<u8>>,
+        decoder: fn(&[u8]) -> io::Result<E>,
+    ) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let base = segments(&dir)?.last().map_or(0, |(id, _)| id + 1);
+        Ok(Self {
+            dir,
+            base,
+            current: Mutex::new(None),
+            recovered: Mutex::new(Vec::new()),
+            encoder,
+            decoder,
+        })
+    }
+
+    fn path(&self, segment: u64) -> PathBuf {
+        self.dir.join(format!("{segment:020}.{EXTENSION}"))
+    }
+
+    fn current(&self) -> MutexGuard<'_, Option<(u64, File)>> {
+        self.current.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Read the elements left by a previous process, their segments are
+    /// removed once batch 0 is consumed.
+    pub(crate) fn recover(&self) -> Vec<E> {
+        let segments = match segments(&self.dir) {
+            Ok(segments) => segments,
+            Err(e) => {
+                log::error!("{self:?} cannot list the segments: {e}");
+                return Vec::new();
+            }
+        };
+        let mut lines: Vec<Vec<u8>> = Vec::new();
+        let mut recovered = Vec::new();
+        for (_, path) in segments.into_iter().filter(|(id, _)| *id < self.base) {
+            match File::open(&path) {
+                Ok(file) => {
+                    for line in BufReader::new(file).split(b'\n') {
+                        match line.as_deref().map(<[u8]>::split_first) {
+                            Ok(Some((&PUSHED, line))) => lines.push(line.to_vec()),
+                            // the encodings of equal elements are equal, any of them will do
+                            Ok(Some((&EVICTED, line))) => {
+                                if let Some(i) = lines.iter().position(|l| l == line) {
+                                    lines.remove(i);
+                                }
+                            }
+                            // a torn line is the entry the previous process did not acknowledge
+                            Ok(_) => log::error!("{self:?} skipped an entry of {}", path.display()),
+                            Err(e) => {
+                                log::error!("{self:?} skipped an entry of {}: {e}", path.display());
+                            }
+                        }
+                    }
+                    recovered.push(path);
+                }
+                Err(e) => log::error!("{self:?} cannot read {}: {e}", path.display()),
+            }
+        }
+        *self
+            .recovered
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = recovered;
+        lines
+            .iter()
+            .filter_map(|line| match (self.decoder)(line) {
+                Ok(e) => Some(e),
+                Err(e) => {
+                    log::error!("{self:?} skipped an element: {e}");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Append `value` to the segment of the batch `batch` and sync it to disk.
+    pub(crate) fn append(&self, batch: u64, value: &E) -> io::Result<()> {
+        self.write(batch, &self.pushed(value)?)
+    }
+
+    /// Log that `evicted` made room for `value` in the batch `batch`, at once.
+    pub(crate) fn replace(&self, batch: u64, evicted: &E, value: &E) -> io::Result<()> {
+        self.write(batch, &self.replaced(evicted, value)?)
+    }
+
+    /// The line of a pushed `value`.
+    pub(crate) fn pushed(&self, value: &E) -> io::Result<Vec<u8>> {
+        let mut lines = Vec::new();
+        self.encode(PUSHED, value, &mut lines)?;
+        Ok(lines)
+    }
+
+    /// The lines of `evicted` making room for `value`.
+    pub(crate) fn replaced(&self, evicted: &E, value: &E) -> io::Result<Vec<u8>> {
+        let mut lines = Vec::new();
+        self.encode(EVICTED, evicted, &mut lines)?;
+        self.encode(PUSHED, value, &mut lines)?;
+        Ok(lines)
+    }
+
+    /// Append the line of `value` tagged with `tag` to `lines`.
+    fn encode(&self, tag: u8, value: &E, lines: &mut Vec<u8>) -> io::Result<()> {
+        lines.push(tag);
+        lines.append(&mut (self.encoder)(value)?);
+        lines.push(b'\n');
+        Ok(())
+    }
+
+    /// Append `lines` to the segment of the batch `batch` and sync it to disk.
+    pub(crate) fn write(&self, batch: u64, lines: &[u8]) -> io::Result<()> {
+        let segment = self.base + batch;
+        let mut current = self.current();
+        let file = match &mut *current {
+            Some((id, file)) if *id == segment => file,
+            other => {
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(self.path(segment))?;
+                &mut other.insert((segment, file)).1
+            }
+        };
+        let written = file.write_all(lines).and_then(|()| file.sync_data());
+        drop(current);
+        written
+    }
+
+    /// Remove the segment of the batch `batch`, the consumer succeeded on it
+    /// or the dead letter took it.
+    pub(crate) fn consumed(&self, batch: u64) {
+        let segment = self.base + batch;
+        let mut current = self.current();
+        if matches!(&*current, Some((id, _)) if *id == segment) {
+            *current = None;
+        }
+        drop(current);
+        let mut paths = vec![self.path(segment)];
+        if batch == 0 {
+            paths.append(&mut mem::take(
+                &mut *self
+                    .recovered
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner),
+            ));
+        }
+        for path in paths {
+            match fs::remove_file(&path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    log::error!("{self:?} cannot remove {}: {e}", path.display());
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The segments in `dir`, in order.
+fn segments(dir: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(EXTENSION) {
+            continue;
+        }
+        if let Some(id) = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .and_then(|s| s.parse().ok())
+        {
+            segments.push((id, path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
@@ -1,10 +1,21 @@
 #[macro_use]
 extern crate lazy_static;
 use buffer_trigger::{
-    self, buffer_trigger_async, buffer_trigger_sync, buffer_trigger_sync::BufferTrigger,
+    self, buffer_trigger_async, buffer_trigger_sync, buffer_trigger_sync::BufferTrigger, Clock,
+    Error, FileDeadLetter, FlushReason, ManualClock, OverflowPolicy, Payload, PushError,
+    RespStorage, RetryPolicy, Storage, TimerMode, TimerWheel,
 };
 use log::LevelFilter;
-use std::{thread, time::Duration};
+use std::{
+    collections::VecDeque,
+    env, fs, io, process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
 use tokio::time::sleep;
 
 lazy_static! {
@@ -54,3 +65,1650 @@ async fn async_test() {
 
     sleep(Duration::from_secs(5)).await;
 }
+
+#[test]
+fn closure_test() {
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let sink = batches.clone();
+    let offset = 100;
+    let trigger = buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+        .name("closure".to_owned())
+        .accumulator(move |c: &mut Vec<i32>, e: i32| c.push(e + offset))
+        .consumer(move |c| sink.lock().unwrap().push(c))
+        .max_len(2)
+        .build();
+
+    for i in 0..4 {
+        trigger.push(i);
+    }
+
+    assert_eq!(
+        *batches.lock().unwrap(),
+        vec![vec![100, 101], vec![102, 103]]
+    );
+}
+
+#[tokio::test]
+async fn async_consumer_test() {
+    let consumed = Arc::new(AtomicUsize::new(0));
+    let counter = consumed.clone();
+    let trigger = buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+        .name("async_consumer".to_owned())
+        .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+        .async_consumer(move |c| {
+            let counter = counter.clone();
+            async move {
+                sleep(Duration::from_millis(50)).await;
+                counter.fetch_add(c.len(), Ordering::SeqCst);
+            }
+        })
+        .build();
+
+    for i in 0..3 {
+        trigger.push(i).await;
+    }
+    trigger.trigger().await;
+
+    assert_eq!(consumed.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn consume_outside_lock_test() {
+    let trigger = Arc::new(
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .name("outside_lock".to_owned())
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(|_| thread::sleep(Duration::from_millis(500)))
+            .build(),
+    );
+    trigger.push(0);
+    let flusher = {
+        let trigger = trigger.clone();
+        thread::spawn(move || trigger.trigger())
+    };
+    thread::sleep(Duration::from_millis(50));
+
+    let start = Instant::now();
+    trigger.push(1);
+    assert!(start.elapsed() < Duration::from_millis(250));
+    assert_eq!(trigger.len(), 1);
+    flusher.join().unwrap();
+}
+
+#[test]
+fn retry_test() {
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let given_up = Arc::new(AtomicUsize::new(0));
+    let trigger = {
+        let attempts = attempts.clone();
+        let given_up = given_up.clone();
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .name("retry".to_owned())
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .try_consumer(move |c| {
+                if attempts.fetch_add(1, Ordering::SeqCst) % 3 < 2 {
+                    let e = format!("failed to consume {c:?}");
+                    Err((c, e))
+                } else {
+                    Ok(())
+                }
+            })
+            .retry_policy(
+                RetryPolicy::new(3)
+                    .backoff(Duration::from_millis(1), Duration::from_millis(10))
+                    .on_give_up(move |_, attempts| {
+                        given_up.fetch_add(attempts as usize, Ordering::SeqCst);
+                    }),
+            )
+            .build()
+    };
+
+    trigger.push(1);
+    trigger.trigger();
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    assert_eq!(given_up.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn async_give_up_test() {
+    let given_up = Arc::new(AtomicUsize::new(0));
+    let trigger = {
+        let given_up = given_up.clone();
+        buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+            .name("give_up".to_owned())
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .try_consumer(|c| async { Err((c, "unavailable")) })
+            .retry_policy(
+                RetryPolicy::new(2)
+                    .backoff(Duration::from_millis(1), Duration::from_millis(10))
+                    .on_give_up(move |_, attempts| {
+                        given_up.fetch_add(attempts as usize, Ordering::SeqCst);
+                    }),
+            )
+            .build()
+    };
+
+    trigger.push(1).await;
+    trigger.trigger().await;
+    assert_eq!(given_up.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn dead_letter_test() {
+    let path = env::temp_dir().join(format!("buffer-trigger-dead-letter-{}", process::id()));
+    let _ = fs::remove_file(&path);
+    let trigger = buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+        .name("dead_letter".to_owned())
+        .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+        .try_consumer(|c| Err((c, "unavailable")))
+        .retry_policy(
+            RetryPolicy::new(2).backoff(Duration::from_millis(1), Duration::from_millis(1)),
+        )
+        .dead_letter(
+            FileDeadLetter::open(&path, |c| serde_json::to_vec(c).map_err(io::Error::from))
+                .unwrap(),
+        )
+        .build();
+
+    trigger.push(1);
+    trigger.push(2);
+    trigger.trigger();
+    trigger.push(3);
+    trigger.trigger();
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "[1,2]\n[3]\n");
+    let batches: Vec<Vec<i32>> = FileDeadLetter::read(&path, |line| {
+        serde_json::from_slice(line).map_err(io::Error::from)
+    })
+    .unwrap();
+    assert_eq!(batches, vec![vec![1, 2], vec![3]]);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn capacity_test() {
+    let trigger = Arc::new(
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .name("capacity".to_owned())
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(|_| thread::sleep(Duration::from_millis(300)))
+            .max_len(2)
+            .capacity(2)
+            .build(),
+    );
+    let flusher = {
+        let trigger = trigger.clone();
+        thread::spawn(move || {
+            trigger.push(1);
+            trigger.push(2);
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+
+    assert_eq!(trigger.try_push(3), Err(PushError::Full(3)));
+    let start = Instant::now();
+    trigger.push(3);
+    assert!(start.elapsed() >= Duration::from_millis(150));
+    assert_eq!(trigger.len(), 1);
+    flusher.join().unwrap();
+}
+
+#[tokio::test]
+async fn async_capacity_test() {
+    let release = Arc::new(tokio::sync::Semaphore::new(0));
+    let trigger = {
+        let release = release.clone();
+        Arc::new(
+            buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+                .name("async_capacity".to_owned())
+                .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+                .async_consumer(move |_| {
+                    let release = release.clone();
+                    async move { drop(release.acquire().await) }
+                })
+                .max_len(2)
+                .capacity(2)
+                .build(),
+        )
+    };
+    // the batch of 1 and 2 holds both slots while its consumer is blocked
+    let flusher = {
+        let trigger = trigger.clone();
+        tokio::spawn(async move {
+            trigger.push(1).await;
+            trigger.push(2).await;
+        })
+    };
+    sleep(Duration::from_millis(50)).await;
+    assert_eq!(trigger.try_push(3).await, Err(PushError::Full(3)));
+
+    let pusher = {
+        let trigger = trigger.clone();
+        tokio::spawn(async move { trigger.push(3).await })
+    };
+    sleep(Duration::from_millis(50)).await;
+    assert!(!pusher.is_finished());
+    release.add_permits(1);
+    tokio::time::timeout(Duration::from_secs(1), pusher)
+        .await
+        .unwrap()
+        .unwrap();
+    flusher.await.unwrap();
+    assert_eq!(trigger.len().await, 1);
+}
+
+#[test]
+fn drop_oldest_test() {
+    let consumed = Arc::new(Mutex::new(Vec::new()));
+    let dropped = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let consumed = consumed.clone();
+        let dropped = dropped.clone();
+        Arc::new(
+            buffer_trigger_sync::SimpleBuilder::builder(VecDeque::default)
+                .name("drop_oldest".to_owned())
+                .accumulator(|c: &mut VecDeque<i32>, e| c.push_back(e))
+                .consumer(move |c| {
+                    thread::sleep(Duration::from_millis(300));
+                    consumed.lock().unwrap().push(Vec::from(c));
+                })
+                .max_len(2)
+                .capacity(3)
+                .overflow_policy(OverflowPolicy::DropOldest(
+                    VecDeque::pop_front,
+                    VecDeque::front,
+                ))
+                .on_drop(move |e| dropped.lock().unwrap().push(e))
+                .build(),
+        )
+    };
+    let flusher = {
+        let trigger = trigger.clone();
+        thread::spawn(move || {
+            trigger.push(1);
+            trigger.push(2);
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+
+    for i in 3..6 {
+        trigger.push(i);
+    }
+    flusher.join().unwrap();
+    trigger.trigger();
+
+    assert_eq!(*consumed.lock().unwrap(), vec![vec![1, 2], vec![5]]);
+    assert_eq!(*dropped.lock().unwrap(), vec![3, 4]);
+    assert_eq!(trigger.dropped(), 2);
+}
+
+#[tokio::test]
+async fn async_reject_test() {
+    let dropped = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let dropped = dropped.clone();
+        Arc::new(
+            buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+                .name("reject".to_owned())
+                .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+                .async_consumer(|_| sleep(Duration::from_millis(300)))
+                .max_len(2)
+                .capacity(2)
+                .overflow_policy(OverflowPolicy::Reject)
+                .on_drop(move |e| dropped.lock().unwrap().push(e))
+                .build(),
+        )
+    };
+    let flusher = {
+        let trigger = trigger.clone();
+        tokio::spawn(async move {
+            trigger.push(1).await;
+            trigger.push(2).await;
+        })
+    };
+    sleep(Duration::from_millis(50)).await;
+
+    trigger.push(3).await;
+    assert_eq!(trigger.try_push(4).await, Err(PushError::Full(4)));
+    assert_eq!(*dropped.lock().unwrap(), vec![3]);
+    assert_eq!(trigger.dropped(), 1);
+    flusher.await.unwrap();
+}
+
+#[test]
+fn drop_newest_test() {
+    let consumed = Arc::new(Mutex::new(Vec::new()));
+    let dropped = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let consumed = consumed.clone();
+        let dropped = dropped.clone();
+        Arc::new(
+            buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+                .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+                .consumer(move |c| {
+                    thread::sleep(Duration::from_millis(300));
+                    consumed.lock().unwrap().push(c);
+                })
+                .max_len(2)
+                .capacity(3)
+                .overflow_policy(OverflowPolicy::DropNewest)
+                .on_drop(move |e| dropped.lock().unwrap().push(e))
+                .build(),
+        )
+    };
+    let flusher = {
+        let trigger = trigger.clone();
+        thread::spawn(move || {
+            trigger.push(1);
+            trigger.push(2);
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+
+    trigger.push(3);
+    trigger.push(4);
+    assert_eq!(trigger.try_push(5), Ok(()));
+    flusher.join().unwrap();
+    trigger.trigger();
+
+    assert_eq!(*consumed.lock().unwrap(), vec![vec![1, 2], vec![3]]);
+    assert_eq!(*dropped.lock().unwrap(), vec![4, 5]);
+    assert_eq!(trigger.dropped(), 2);
+}
+
+#[tokio::test]
+async fn async_drop_oldest_test() {
+    let consumed = Arc::new(Mutex::new(Vec::new()));
+    let dropped = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let consumed = consumed.clone();
+        let dropped = dropped.clone();
+        Arc::new(
+            buffer_trigger_async::SimpleBuilder::builder(VecDeque::default)
+                .accumulator(|c: &mut VecDeque<i32>, e| c.push_back(e))
+                .async_consumer(move |c| {
+                    let consumed = consumed.clone();
+                    async move {
+                        sleep(Duration::from_millis(300)).await;
+                        consumed.lock().unwrap().push(Vec::from(c));
+                    }
+                })
+                .max_len(2)
+                .capacity(3)
+                .overflow_policy(OverflowPolicy::DropOldest(
+                    VecDeque::pop_front,
+                    VecDeque::front,
+                ))
+                .on_drop(move |e| dropped.lock().unwrap().push(e))
+                .build(),
+        )
+    };
+    let flusher = {
+        let trigger = trigger.clone();
+        tokio::spawn(async move {
+            trigger.push(1).await;
+            trigger.push(2).await;
+        })
+    };
+    sleep(Duration::from_millis(50)).await;
+
+    trigger.push(3).await;
+    trigger.push(4).await;
+    assert_eq!(trigger.try_push(5).await, Ok(()));
+    flusher.await.unwrap();
+    trigger.trigger().await;
+
+    assert_eq!(*consumed.lock().unwrap(), vec![vec![1, 2], vec![5]]);
+    assert_eq!(*dropped.lock().unwrap(), vec![3, 4]);
+    assert_eq!(trigger.dropped(), 2);
+}
+
+#[tokio::test]
+async fn async_cancelled_push_test() {
+    let consumed = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let consumed = consumed.clone();
+        buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .async_consumer(move |c| {
+                let consumed = consumed.clone();
+                async move {
+                    sleep(Duration::from_millis(200)).await;
+                    consumed.lock().unwrap().push(c);
+                }
+            })
+            .capacity(2)
+            .build()
+    };
+
+    trigger.push(1).await;
+    // cancelled while it flushes the full buffer
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), trigger.push(2))
+            .await
+            .is_err()
+    );
+    sleep(Duration::from_millis(300)).await;
+    // the consumer runs to completion all the same
+    assert_eq!(*consumed.lock().unwrap(), vec![vec![1, 2]]);
+    assert_eq!(trigger.try_push(3).await, Ok(()));
+    assert_eq!(trigger.try_push(4).await, Ok(()));
+}
+
+#[tokio::test]
+async fn async_drop_newest_test() {
+    let release = Arc::new(tokio::sync::Semaphore::new(0));
+    let dropped = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let release = release.clone();
+        let dropped = dropped.clone();
+        Arc::new(
+            buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+                .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+                .async_consumer(move |_| {
+                    let release = release.clone();
+                    async move { drop(release.acquire().await) }
+                })
+                .max_len(2)
+                .capacity(2)
+                .overflow_policy(OverflowPolicy::DropNewest)
+                .on_drop(move |e| dropped.lock().unwrap().push(e))
+                .build(),
+        )
+    };
+    let flusher = {
+        let trigger = trigger.clone();
+        tokio::spawn(async move {
+            trigger.push(1).await;
+            trigger.push(2).await;
+        })
+    };
+    sleep(Duration::from_millis(50)).await;
+
+    trigger.push(3).await;
+    assert_eq!(trigger.try_push(4).await, Ok(()));
+    release.add_permits(1);
+    flusher.await.unwrap();
+    assert!(trigger.is_empty().await);
+    assert_eq!(*dropped.lock().unwrap(), vec![3, 4]);
+    assert_eq!(trigger.dropped(), 2);
+}
+
+#[test]
+fn weight_test() {
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let batches = batches.clone();
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .name("weight".to_owned())
+            .accumulator(|c: &mut Vec<&str>, e| c.push(e))
+            .consumer(move |c| batches.lock().unwrap().push(c))
+            .weigher(|e| e.len())
+            .max_weight(10)
+            .build()
+    };
+
+    trigger.push("abcd");
+    trigger.push("efgh");
+    assert_eq!(trigger.weight(), 8);
+    trigger.push("ijk");
+    assert_eq!(trigger.weight(), 3);
+    trigger.push("lmnopqr");
+    assert_eq!(trigger.weight(), 0);
+
+    assert_eq!(
+        *batches.lock().unwrap(),
+        vec![vec!["abcd", "efgh"], vec!["ijk", "lmnopqr"]]
+    );
+}
+
+#[test]
+fn keyed_test() {
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let batches = batches.clone();
+        buffer_trigger_sync::KeyedBuilder::builder(Vec::default)
+            .name("keyed".to_owned())
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(move |k: &str, c| batches.lock().unwrap().push((k, c)))
+            .max_len(2)
+            .interval(Duration::from_millis(200))
+            .build()
+    };
+
+    trigger.push("a", 1);
+    trigger.push("b", 2);
+    trigger.push("a", 3);
+    assert_eq!(*batches.lock().unwrap(), vec![("a", vec![1, 3])]);
+    assert_eq!(trigger.keys(), 1);
+
+    thread::sleep(Duration::from_millis(400));
+    assert_eq!(
+        *batches.lock().unwrap(),
+        vec![("a", vec![1, 3]), ("b", vec![2])]
+    );
+    assert!(trigger.is_empty());
+    assert_eq!(trigger.keys(), 0);
+}
+
+#[tokio::test]
+async fn async_keyed_test() {
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let batches = batches.clone();
+        buffer_trigger_async::KeyedBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(move |k: u8, c| batches.lock().unwrap().push((k, c)))
+            .interval(Duration::from_millis(100))
+            .build()
+    };
+
+    trigger.push(1, 1).await;
+    trigger.push(2, 2).await;
+    trigger.trigger_key(&2).await;
+    assert_eq!(*batches.lock().unwrap(), vec![(2, vec![2])]);
+
+    sleep(Duration::from_millis(300)).await;
+    assert_eq!(*batches.lock().unwrap(), vec![(2, vec![2]), (1, vec![1])]);
+    assert_eq!(trigger.keys().await, 0);
+}
+
+#[test]
+fn keyed_drop_test() {
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let batches = batches.clone();
+        buffer_trigger_sync::KeyedBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(move |k: u8, c| batches.lock().unwrap().push((k, c)))
+            .interval(Duration::from_secs(10))
+            .build()
+    };
+
+    trigger.push(1, 1);
+    trigger.push(2, 2);
+    trigger.push(1, 3);
+    drop(trigger);
+    let mut batches = batches.lock().unwrap().clone();
+    batches.sort_unstable();
+    assert_eq!(batches, vec![(1, vec![1, 3]), (2, vec![2])]);
+}
+
+#[tokio::test]
+async fn async_keyed_drop_test() {
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let batches = batches.clone();
+        buffer_trigger_async::KeyedBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .async_consumer(move |k: u8, c| {
+                let batches = batches.clone();
+                async move {
+                    sleep(Duration::from_millis(10)).await;
+                    batches.lock().unwrap().push((k, c));
+                }
+            })
+            .interval(Duration::from_secs(10))
+            .build()
+    };
+
+    trigger.push(1, 1).await;
+    trigger.push(2, 2).await;
+    trigger.push(1, 3).await;
+    drop(trigger);
+    let mut batches = batches.lock().unwrap().clone();
+    batches.sort_unstable();
+    assert_eq!(batches, vec![(1, vec![1, 3]), (2, vec![2])]);
+}
+
+#[test]
+fn batch_info_test() {
+    let infos = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let infos = infos.clone();
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .name("info".to_owned())
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer_with_info(move |c, info| {
+                assert_eq!(c.len(), info.len);
+                infos.lock().unwrap().push(info.clone());
+            })
+            .max_len(2)
+            .interval(Duration::from_millis(100))
+            .build()
+    };
+
+    trigger.push(1);
+    trigger.push(2);
+    trigger.push(3);
+    thread::sleep(Duration::from_millis(300));
+    trigger.push(4);
+    trigger.trigger();
+
+    let infos = infos.lock().unwrap();
+    let reasons: Vec<_> = infos
+        .iter()
+        .map(|i| (i.batch_id, i.reason, i.len))
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            (0, FlushReason::MaxLen, 2),
+            (1, FlushReason::Clock, 1),
+            (2, FlushReason::Manual, 1)
+        ]
+    );
+    assert!(infos.iter().all(|i| i.trigger_name == "info"));
+    assert!(infos[1].flushed_at - infos[1].first_push_at >= Duration::from_millis(100));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn async_shutdown_test() {
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let build = || {
+        let batches = batches.clone();
+        buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .async_consumer_with_info(move |c, info| {
+                let batches = batches.clone();
+                async move {
+                    sleep(Duration::from_millis(50)).await;
+                    batches.lock().unwrap().push((c, info.reason));
+                }
+            })
+            .interval(Duration::from_secs(10))
+            .build()
+    };
+
+    let trigger = build();
+    trigger.push(1).await;
+    let report = trigger.shutdown(Duration::from_secs(1)).await;
+    assert!(report.is_complete());
+    assert_eq!(report.flushed, 1);
+    trigger.push(3).await;
+    assert_eq!(trigger.dropped(), 1);
+    assert_eq!(trigger.try_push(4).await, Err(PushError::Closed(4)));
+    assert_eq!(
+        *batches.lock().unwrap(),
+        vec![(vec![1], FlushReason::Shutdown)]
+    );
+
+    let trigger = build();
+    trigger.push(4).await;
+    let report = trigger.shutdown(Duration::from_millis(10)).await;
+    assert_eq!((report.flushed, report.left), (0, 1));
+
+    // a failed batch is not reported as flushed
+    let trigger = buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+        .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+        .try_consumer(|c| async { Err((c, "unavailable")) })
+        .build();
+    trigger.push(5).await;
+    let report = trigger.shutdown(Duration::from_secs(1)).await;
+    assert_eq!((report.flushed, report.left), (0, 1));
+
+    let trigger = build();
+    trigger.push(2).await;
+    // let the listener start waiting for the clock
+    sleep(Duration::from_millis(50)).await;
+    drop(trigger);
+    sleep(Duration::from_millis(300)).await;
+    assert_eq!(
+        *batches.lock().unwrap(),
+        vec![
+            (vec![1], FlushReason::Shutdown),
+            (vec![2], FlushReason::Drop)
+        ]
+    );
+}
+
+#[test]
+fn shutdown_test() {
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let batches = batches.clone();
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(move |c| batches.lock().unwrap().push(c))
+            .interval(Duration::from_secs(10))
+            .build()
+    };
+
+    trigger.push(1);
+    trigger.push(2);
+    let start = Instant::now();
+    let report = trigger.shutdown(Duration::from_secs(5));
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert_eq!(report.flushed, 2);
+    assert!(report.is_complete());
+    assert_eq!(*batches.lock().unwrap(), vec![vec![1, 2]]);
+
+    trigger.push(3);
+    assert!(trigger.is_empty());
+    assert_eq!(trigger.dropped(), 1);
+    assert_eq!(trigger.try_push(4), Err(PushError::Closed(4)));
+
+    // a failed batch is not reported as flushed
+    let trigger = buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+        .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+        .try_consumer(|c| Err((c, "unavailable")))
+        .build();
+    trigger.push(5);
+    let report = trigger.shutdown(Duration::from_secs(1));
+    assert_eq!((report.flushed, report.left), (0, 1));
+    assert!(!report.is_complete());
+}
+
+#[test]
+fn error_test() {
+    let trigger = Arc::new(
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| {
+                assert!(e >= 0, "negative element");
+                c.push(e);
+            })
+            .try_consumer(|c| Err((c, "unavailable")))
+            .build(),
+    );
+
+    trigger.push(1);
+    assert_eq!(
+        trigger.try_trigger(),
+        Err(Error::ConsumerFailed {
+            attempts: 1,
+            reason: "unavailable".to_owned()
+        })
+    );
+    assert_eq!(trigger.try_len(), Ok(0));
+
+    let poisoner = trigger.clone();
+    assert!(thread::spawn(move || poisoner.push(-1)).join().is_err());
+    assert_eq!(trigger.try_len(), Err(Error::Poisoned));
+    assert_eq!(trigger.try_trigger(), Err(Error::Poisoned));
+    let e = trigger.try_push(2).unwrap_err();
+    assert_eq!(Error::from(&e), Error::Poisoned);
+    assert_eq!(e.into_inner(), 2);
+}
+
+#[test]
+fn panic_test() {
+    let panicked = Arc::new(Mutex::new(Vec::new()));
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let panicked = panicked.clone();
+        let batches = batches.clone();
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(move |c| {
+                assert!(!c.contains(&0), "zero in batch");
+                batches.lock().unwrap().push(c);
+            })
+            .on_panic(move |info, reason| {
+                panicked.lock().unwrap().push((info.len, reason.to_owned()))
+            })
+            .capacity(2)
+            .build()
+    };
+
+    trigger.push(0);
+    assert_eq!(
+        trigger.try_trigger(),
+        Err(Error::ConsumerPanicked {
+            reason: "zero in batch".to_owned()
+        })
+    );
+    assert_eq!(trigger.panics(), 1);
+    assert_eq!(
+        *panicked.lock().unwrap(),
+        vec![(1, "zero in batch".to_owned())]
+    );
+
+    // the capacity slot was released and the trigger keeps working
+    trigger.push(1);
+    assert_eq!(trigger.try_trigger(), Ok(()));
+    trigger.push(2);
+    trigger.push(3);
+    assert_eq!(*batches.lock().unwrap(), vec![vec![1], vec![2, 3]]);
+}
+
+#[tokio::test]
+async fn async_panic_test() {
+    let consumed = Arc::new(AtomicUsize::new(0));
+    let trigger = {
+        let consumed = consumed.clone();
+        buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .async_consumer(move |c| {
+                let consumed = consumed.clone();
+                async move {
+                    assert!(!c.contains(&0), "zero in batch");
+                    consumed.fetch_add(c.len(), Ordering::SeqCst);
+                }
+            })
+            .build()
+    };
+
+    trigger.push(0).await;
+    assert_eq!(
+        trigger.try_trigger().await,
+        Err(Error::ConsumerPanicked {
+            reason: "zero in batch".to_owned()
+        })
+    );
+    assert_eq!(trigger.panics(), 1);
+
+    trigger.push(1).await;
+    trigger.push(2).await;
+    assert_eq!(trigger.try_trigger().await, Ok(()));
+    assert_eq!(consumed.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn clock_test() {
+    let infos = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let infos = infos.clone();
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer_with_info(move |_, info| infos.lock().unwrap().push(info.clone()))
+            .interval(Duration::from_millis(200))
+            .build()
+    };
+
+    for window in 0..3 {
+        trigger.push(window);
+        thread::sleep(Duration::from_millis(100));
+        trigger.push(window);
+        assert_eq!(trigger.len(), 2);
+        thread::sleep(Duration::from_millis(250));
+        assert!(trigger.is_empty());
+    }
+
+    let infos = infos.lock().unwrap();
+    assert_eq!(infos.len(), 3);
+    for info in infos.iter() {
+        assert_eq!((info.reason, info.len), (FlushReason::Clock, 2));
+        let age = info.flushed_at - info.first_push_at;
+        assert!(age >= Duration::from_millis(200) && age < Duration::from_millis(300));
+    }
+}
+
+#[tokio::test]
+async fn async_stale_clock_test() {
+    let infos = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let infos = infos.clone();
+        buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .async_consumer_with_info(move |_, info| {
+                infos.lock().unwrap().push(info);
+                async {}
+            })
+            .max_len(2)
+            .interval(Duration::from_millis(200))
+            .build()
+    };
+
+    trigger.push(1).await;
+    trigger.push(2).await;
+    sleep(Duration::from_millis(150)).await;
+    trigger.push(3).await;
+    // the timer of the first window fires here and is ignored
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(trigger.len().await, 1);
+    sleep(Duration::from_millis(200)).await;
+    assert!(trigger.is_empty().await);
+
+    let infos = infos.lock().unwrap();
+    let reasons: Vec<_> = infos.iter().map(|i| (i.reason, i.len)).collect();
+    assert_eq!(
+        reasons,
+        vec![(FlushReason::MaxLen, 2), (FlushReason::Clock, 1)]
+    );
+    assert!(infos[1].flushed_at - infos[1].first_push_at >= Duration::from_millis(200));
+}
+
+#[test]
+fn timer_wheel_test() {
+    let wheel = TimerWheel::new(Duration::from_millis(1));
+    let consumed = Arc::new(Mutex::new(Vec::new()));
+    let triggers: Vec<_> = (0..100)
+        .map(|i| {
+            let consumed = consumed.clone();
+            buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+                .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+                .consumer_with_info(move |c, info| {
+                    assert_eq!(info.reason, FlushReason::Clock);
+                    assert!(info.flushed_at - info.first_push_at >= Duration::from_millis(100));
+                    consumed.lock().unwrap().extend(c);
+                })
+                .interval(Duration::from_millis(100 + i))
+                .timer(wheel.clone())
+                .build()
+        })
+        .collect();
+
+    for (i, trigger) in (0..).zip(&triggers) {
+        trigger.push(i);
+    }
+    thread::sleep(Duration::from_millis(50));
+    assert!(consumed.lock().unwrap().is_empty());
+    thread::sleep(Duration::from_millis(300));
+    let mut consumed = consumed.lock().unwrap().clone();
+    consumed.sort_unstable();
+    assert_eq!(consumed, (0..100).collect::<Vec<_>>());
+    assert!(triggers.iter().all(buffer_trigger_sync::Simple::is_empty));
+}
+
+#[test]
+fn timer_wheel_slow_consumer_test() {
+    let wheel = TimerWheel::default();
+    let slow = buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+        .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+        .consumer(|_| thread::sleep(Duration::from_secs(1)))
+        .interval(Duration::from_millis(10))
+        .timer(wheel.clone())
+        .build();
+    let consumed = Arc::new(AtomicUsize::new(0));
+    let fast = {
+        let consumed = consumed.clone();
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(move |c| {
+                consumed.fetch_add(c.len(), Ordering::SeqCst);
+            })
+            .interval(Duration::from_millis(50))
+            .timer(wheel)
+            .build()
+    };
+
+    slow.push(1);
+    fast.push(2);
+    // the slow consumer does not hold up the wheel thread
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(consumed.load(Ordering::SeqCst), 1);
+    assert!(fast.is_empty());
+}
+
+#[test]
+fn timer_wheel_dropped_trigger_test() {
+    let wheel = TimerWheel::default();
+    let flushed = Arc::new(AtomicUsize::new(0));
+    let slow = {
+        let flushed = flushed.clone();
+        buffer_trigger_sync::GeneralBuilder::builder(Payload::new(
+            Vec::default,
+            |c: &mut Vec<i32>, e| c.push(e),
+        ))
+        .consumer(move |c| {
+            thread::sleep(Duration::from_secs(1));
+            flushed.fetch_add(c.len(), Ordering::SeqCst);
+        })
+        .interval(Duration::from_secs(10))
+        .timer(wheel.clone())
+        .build()
+    };
+    let consumed = Arc::new(AtomicUsize::new(0));
+    let fast = {
+        let consumed = consumed.clone();
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(move |c| {
+                consumed.fetch_add(c.len(), Ordering::SeqCst);
+            })
+            .interval(Duration::from_millis(50))
+            .timer(wheel)
+            .build()
+    };
+
+    slow.push(1);
+    drop(slow);
+    // the wheel lets go of the dropped trigger within a second, its flush
+    // does not hold up the wheel thread
+    thread::sleep(Duration::from_millis(1200));
+    fast.push(2);
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(consumed.load(Ordering::SeqCst), 1);
+    thread::sleep(Duration::from_secs(1));
+    assert_eq!(flushed.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn async_timer_wheel_test() {
+    let wheel = TimerWheel::default();
+    let consumed = Arc::new(AtomicUsize::new(0));
+    let trigger = {
+        let consumed = consumed.clone();
+        buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(move |c| {
+                consumed.fetch_add(c.len(), Ordering::SeqCst);
+            })
+            .max_len(2)
+            .interval(Duration::from_millis(100))
+            .timer(wheel)
+            .build()
+    };
+
+    trigger.push(1).await;
+    trigger.push(2).await;
+    sleep(Duration::from_millis(50)).await;
+    trigger.push(3).await;
+    // the deadline of the first window is stale
+    sleep(Duration::from_millis(80)).await;
+    assert_eq!(trigger.len().await, 1);
+    sleep(Duration::from_millis(100)).await;
+    assert!(trigger.is_empty().await);
+    assert_eq!(consumed.load(Ordering::SeqCst), 3);
+
+    trigger.push(4).await;
+    drop(trigger);
+    assert_eq!(consumed.load(Ordering::SeqCst), 4);
+}
+
+#[test]
+fn debounce_test() {
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let batches = batches.clone();
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(move |c| batches.lock().unwrap().push(c))
+            .interval(Duration::from_millis(100))
+            .timer_mode(TimerMode::Debounce { max_wait: None })
+            .build()
+    };
+
+    for i in 0..5 {
+        trigger.push(i);
+        thread::sleep(Duration::from_millis(50));
+    }
+    assert!(batches.lock().unwrap().is_empty());
+    thread::sleep(Duration::from_millis(150));
+    assert_eq!(*batches.lock().unwrap(), vec![vec![0, 1, 2, 3, 4]]);
+}
+
+#[test]
+fn debounce_max_wait_test() {
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let batches = batches.clone();
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(move |c| batches.lock().unwrap().push(c))
+            .interval(Duration::from_secs(3))
+            .timer_mode(TimerMode::Debounce {
+                max_wait: Some(Duration::from_millis(200)),
+            })
+            .build()
+    };
+
+    // the listener is already waiting for an `interval` when the window opens
+    thread::sleep(Duration::from_millis(50));
+    trigger.push(1);
+    thread::sleep(Duration::from_millis(400));
+    assert_eq!(*batches.lock().unwrap(), vec![vec![1]]);
+}
+
+#[tokio::test]
+async fn async_debounce_test() {
+    let infos = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let infos = infos.clone();
+        buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .async_consumer_with_info(move |_, info| {
+                infos.lock().unwrap().push(info);
+                async {}
+            })
+            .interval(Duration::from_millis(100))
+            .timer_mode(TimerMode::Debounce {
+                max_wait: Some(Duration::from_millis(300)),
+            })
+            .build()
+    };
+
+    // constant traffic is still flushed after `max_wait`
+    for i in 0..10 {
+        trigger.push(i).await;
+        sleep(Duration::from_millis(50)).await;
+    }
+    sleep(Duration::from_millis(150)).await;
+    assert!(trigger.is_empty().await);
+
+    let infos = infos.lock().unwrap();
+    assert!(infos.len() >= 2);
+    for info in infos.iter() {
+        assert_eq!(info.reason, FlushReason::Clock);
+        assert!(info.flushed_at - info.first_push_at < Duration::from_millis(350));
+    }
+}
+
+#[test]
+fn ticker_test() {
+    let clock = ManualClock::new();
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let (batches, now) = (batches.clone(), clock.clone());
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(move |c| {
+                let since_epoch = now.system_time().duration_since(UNIX_EPOCH).unwrap();
+                batches.lock().unwrap().push((since_epoch.as_millis(), c));
+            })
+            .interval(Duration::from_millis(100))
+            .timer_mode(TimerMode::Ticker { heartbeat: true })
+            .clock(clock.clone())
+            .build()
+    };
+
+    trigger.push(1);
+    clock.advance(Duration::from_millis(450));
+    // flushed on the wall-clock boundaries, the first one carries the element
+    assert_eq!(
+        *batches.lock().unwrap(),
+        vec![(100, vec![1]), (200, vec![]), (300, vec![]), (400, vec![])]
+    );
+}
+
+#[tokio::test]
+async fn async_ticker_test() {
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let batches = batches.clone();
+        buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(move |c| batches.lock().unwrap().push(c))
+            .interval(Duration::from_millis(100))
+            .timer_mode(TimerMode::Ticker { heartbeat: false })
+            .timer(TimerWheel::default())
+            .build()
+    };
+
+    trigger.push(1).await;
+    trigger.push(2).await;
+    sleep(Duration::from_millis(350)).await;
+    trigger.push(3).await;
+    sleep(Duration::from_millis(150)).await;
+    assert_eq!(*batches.lock().unwrap(), vec![vec![1, 2], vec![3]]);
+}
+
+#[test]
+fn manual_clock_test() {
+    let clock = ManualClock::new();
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let batches = batches.clone();
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(move |c| batches.lock().unwrap().push(c))
+            .interval(Duration::from_secs(1))
+            .timer_mode(TimerMode::Debounce { max_wait: None })
+            .clock(clock.clone())
+            .build()
+    };
+
+    trigger.push(1);
+    clock.advance(Duration::from_millis(600));
+    trigger.push(2);
+    clock.advance(Duration::from_millis(600));
+    // the second push moved the deadline on
+    assert!(batches.lock().unwrap().is_empty());
+    clock.advance(Duration::from_millis(400));
+    assert_eq!(*batches.lock().unwrap(), vec![vec![1, 2]]);
+    trigger.push(3);
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(*batches.lock().unwrap(), vec![vec![1, 2], vec![3]]);
+
+    // the backoff of a retry sleeps on the clock
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let trigger = {
+        let attempts = attempts.clone();
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .try_consumer(move |c| {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Err((c, "unavailable"))
+                } else {
+                    Ok(())
+                }
+            })
+            .retry_policy(
+                RetryPolicy::new(2).backoff(Duration::from_secs(60), Duration::from_secs(60)),
+            )
+            .clock(clock.clone())
+            .build()
+    };
+    let start = clock.now();
+    trigger.push(4);
+    let started = Instant::now();
+    assert_eq!(trigger.try_trigger(), Ok(()));
+    assert!(started.elapsed() < Duration::from_secs(1));
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    // jittered
+    let slept = clock.now() - start;
+    assert!(slept > Duration::ZERO && slept <= Duration::from_secs(60));
+}
+
+#[tokio::test]
+async fn async_manual_clock_test() {
+    let clock = ManualClock::new();
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let batches = batches.clone();
+        buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .async_consumer(move |c| {
+                let batches = batches.clone();
+                async move {
+                    sleep(Duration::from_millis(10)).await;
+                    batches.lock().unwrap().push(c);
+                }
+            })
+            .interval(Duration::from_secs(60))
+            .clock(clock.clone())
+            .build()
+    };
+
+    trigger.push(1).await;
+    clock.advance(Duration::from_secs(59));
+    assert!(batches.lock().unwrap().is_empty());
+    // `advance` returns once the async consumer is done
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(*batches.lock().unwrap(), vec![vec![1]]);
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn recorder_test() {
+    use buffer_trigger::testing::Recorder;
+
+    let recorder = Recorder::new();
+    let trigger = buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+        .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+        .consumer_with_info(recorder.consumer())
+        .max_len(2)
+        .interval(Duration::from_millis(100))
+        .build();
+
+    trigger.push(1);
+    trigger.push(2);
+    trigger.push(3);
+    assert!(recorder.wait_for_batches(2, Duration::from_secs(1)));
+    assert!(!recorder.wait_for_batches(3, Duration::from_millis(200)));
+    recorder.assert_sizes(&[2, 1]);
+    recorder.assert_reasons(&[FlushReason::MaxLen, FlushReason::Clock]);
+    assert_eq!(recorder.batches(), vec![vec![1, 2], vec![3]]);
+}
+
+#[cfg(feature = "testing")]
+#[tokio::test]
+async fn async_recorder_test() {
+    use buffer_trigger::testing::Recorder;
+
+    let recorder = Recorder::new();
+    let trigger = buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+        .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+        .async_consumer_with_info(recorder.async_consumer())
+        .interval(Duration::from_millis(100))
+        .build();
+
+    trigger.push(1).await;
+    trigger.trigger().await;
+    trigger.push(2).await;
+    assert!(
+        recorder
+            .wait_for_batches_async(2, Duration::from_secs(1))
+            .await
+    );
+    recorder.assert_sizes(&[1, 1]);
+    recorder.assert_reasons(&[FlushReason::Manual, FlushReason::Clock]);
+    assert_eq!(recorder.take().len(), 2);
+    assert!(recorder.is_empty());
+}
+
+/// Keeps the sum of the pushed elements rather than the elements.
+#[derive(Debug, Default)]
+struct Sum {
+    len: usize,
+    total: i64,
+}
+
+impl Storage<i64> for Sum {
+    type Batch = i64;
+
+    fn push(&mut self, value: i64) {
+        self.len += 1;
+        self.total += value;
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn take_batch(&mut self) -> i64 {
+        self.len = 0;
+        std::mem::take(&mut self.total)
+    }
+}
+
+#[test]
+fn storage_test() {
+    let sums = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let sums = sums.clone();
+        buffer_trigger_sync::GeneralBuilder::builder(Sum::default())
+            .consumer(move |total| sums.lock().unwrap().push(total))
+            .max_len(3)
+            .build()
+    };
+
+    for i in 1..=7 {
+        trigger.push(i);
+    }
+    assert_eq!(trigger.len(), 1);
+    trigger.trigger();
+    assert_eq!(*sums.lock().unwrap(), vec![6, 15, 7]);
+
+    // the in-memory storage of `Simple`, used directly
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let batches = batches.clone();
+        buffer_trigger_sync::GeneralBuilder::builder(Payload::new(
+            Vec::default,
+            |c: &mut Vec<i32>, e| c.push(e),
+        ))
+        .consumer(move |c| batches.lock().unwrap().push(c))
+        .max_len(2)
+        .build()
+    };
+    trigger.push(1);
+    trigger.push(2);
+    assert_eq!(*batches.lock().unwrap(), vec![vec![1, 2]]);
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn resp_storage_test() {
+    use buffer_trigger::testing::RespServer;
+
+    fn storage(server: &RespServer, key: &str) -> RespStorage<i32> {
+        RespStorage::connect(
+            server.addr(),
+            key,
+            |e: &i32| Ok(e.to_string().into_bytes()),
+            |data| {
+                let text = std::str::from_utf8(data).map_err(io::Error::other)?;
+                text.parse().map_err(io::Error::other)
+            },
+        )
+        .unwrap()
+    }
+
+    let server = RespServer::start().unwrap();
+    let batches = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let batches = batches.clone();
+        buffer_trigger_sync::GeneralBuilder::builder(storage(&server, "events"))
+            .consumer(move |c| batches.lock().unwrap().push(c))
+            .max_len(3)
+            .build()
+    };
+
+    for i in 1..=4 {
+        trigger.push(i);
+    }
+    assert_eq!(*batches.lock().unwrap(), vec![vec![1, 2, 3]]);
+    assert_eq!(server.list("events"), vec![b"4".to_vec()]);
+
+    // another process sees and takes the shared buffer
+    let other = Arc::new(Mutex::new(Vec::new()));
+    let other_trigger = {
+        let other = other.clone();
+        buffer_trigger_sync::GeneralBuilder::builder(storage(&server, "events"))
+            .consumer(move |c| other.lock().unwrap().push(c))
+            .build()
+    };
+    assert_eq!(other_trigger.len(), 1);
+    other_trigger.trigger();
+    assert_eq!(*other.lock().unwrap(), vec![vec![4]]);
+    assert!(server.list("events").is_empty());
+
+    // a broken connection keeps the element in memory, the next push reconnects
+    server.disconnect();
+    trigger.push(5);
+    trigger.push(6);
+    assert_eq!(server.list("events"), vec![b"6".to_vec()]);
+    assert_eq!(trigger.len(), 2);
+    trigger.trigger();
+    assert_eq!(batches.lock().unwrap()[1], vec![5, 6]);
+    assert!(server.list("events:processing").is_empty());
+
+    // a batch the connection broke before deleting is taken again first
+    storage(&server, "events:processing").push(7);
+    trigger.push(8);
+    trigger.trigger();
+    assert_eq!(batches.lock().unwrap()[2], vec![7]);
+    assert_eq!(trigger.len(), 1);
+    trigger.trigger();
+    assert_eq!(batches.lock().unwrap()[3], vec![8]);
+}
+
+#[cfg(feature = "wal")]
+#[test]
+fn wal_test() {
+    use buffer_trigger::testing::Recorder;
+    use buffer_trigger::Wal;
+
+    let dir = env::temp_dir().join(format!("buffer-trigger-wal-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    {
+        // the consumer fails, as if the process crashed before it succeeded
+        let trigger = buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .try_consumer(|c| Err((c, "unavailable")))
+            .max_len(2)
+            .wal(Wal::open(&dir).unwrap())
+            .build();
+        trigger.push(1);
+        trigger.push(2);
+        trigger.push(3);
+    }
+
+    let recorder = Recorder::new();
+    let trigger = buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+        .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+        .consumer_with_info(recorder.consumer())
+        .wal(Wal::open(&dir).unwrap())
+        .build();
+    assert_eq!(recorder.batches(), vec![vec![1, 2, 3]]);
+    recorder.assert_reasons(&[FlushReason::Replay]);
+    trigger.push(4);
+    trigger.trigger();
+    assert_eq!(recorder.len(), 2);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    drop(trigger);
+
+    // a dead-lettered batch is not replayed either
+    let dead = Arc::new(Mutex::new(Vec::new()));
+    let trigger = {
+        let dead = dead.clone();
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .try_consumer(|c| Err((c, "unavailable")))
+            .dead_letter(move |c, _, _| dead.lock().unwrap().push(c))
+            .wal(Wal::open(&dir).unwrap())
+            .build()
+    };
+    trigger.push(5);
+    assert!(trigger.try_trigger().is_err());
+    assert_eq!(*dead.lock().unwrap(), vec![vec![5]]);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "wal")]
+#[test]
+fn wal_capacity_test() {
+    use buffer_trigger::Wal;
+
+    let dir = env::temp_dir().join(format!("buffer-trigger-wal-capacity-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    {
+        let trigger = buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .try_consumer(|c| Err((c, "unavailable")))
+            .wal(Wal::open(&dir).unwrap())
+            .build();
+        trigger.push(1);
+        trigger.push(2);
+        trigger.push(3);
+    }
+
+    // more elements are replayed than the capacity, they take no slot
+    let trigger = Arc::new(
+        buffer_trigger_sync::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+            .consumer(|c| {
+                if c != vec![1, 2, 3] {
+                    thread::sleep(Duration::from_millis(300));
+                }
+            })
+            .capacity(2)
+            .wal(Wal::open(&dir).unwrap())
+            .build(),
+    );
+    let flusher = {
+        let trigger = trigger.clone();
+        thread::spawn(move || {
+            trigger.push(4);
+            trigger.push(5);
+        })
+    };
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(trigger.try_push(6), Err(PushError::Full(6)));
+    flusher.join().unwrap();
+    assert_eq!(trigger.try_push(6), Ok(()));
+    drop(trigger);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "wal")]
+#[tokio::test]
+async fn async_wal_eviction_test() {
+    use buffer_trigger::testing::Recorder;
+    use buffer_trigger::Wal;
+
+    let dir = env::temp_dir().join(format!("buffer-trigger-wal-eviction-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    {
+        // the consumer fails, as if the process crashed before it succeeded
+        let trigger = Arc::new(
+            buffer_trigger_async::SimpleBuilder::builder(VecDeque::default)
+                .accumulator(|c: &mut VecDeque<i32>, e| c.push_back(e))
+                .try_consumer(|c| async {
+                    sleep(Duration::from_millis(300)).await;
+                    Err((c, "unavailable"))
+                })
+                .capacity(4)
+                .overflow_policy(OverflowPolicy::DropOldest(
+                    VecDeque::pop_front,
+                    VecDeque::front,
+                ))
+                .wal(
+                    Wal::with_codec(
+                        &dir,
+                        |e: &i32| match e {
+                            99 => Err(io::Error::other("cannot be encoded")),
+                            e => serde_json::to_vec(e).map_err(io::Error::from),
+                        },
+                        |line| serde_json::from_slice(line).map_err(io::Error::from),
+                    )
+                    .unwrap(),
+                )
+                .build(),
+        );
+        let flusher = {
+            let trigger = trigger.clone();
+            tokio::spawn(async move {
+                trigger.push(1).await;
+                trigger.push(2).await;
+                trigger.trigger().await;
+            })
+        };
+        sleep(Duration::from_millis(50)).await;
+        trigger.push(3).await;
+        trigger.push(5).await;
+        // the eviction cannot be logged, the batch is left as it was
+        assert_eq!(trigger.try_push(99).await, Err(PushError::Wal(99)));
+        assert_eq!(trigger.len().await, 2);
+        // evicts 3
+        trigger.push(4).await;
+        assert_eq!(trigger.dropped(), 1);
+        flusher.await.unwrap();
+    }
+
+    let recorder = Recorder::new();
+    let _trigger = buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+        .accumulator(|c: &mut Vec<i32>, e| c.push(e))
+        .async_consumer_with_info(recorder.async_consumer())
+        .wal(Wal::open(&dir).unwrap())
+        .build();
+    assert!(
+        recorder
+            .wait_for_batches_async(1, Duration::from_secs(1))
+            .await
+    );
+    assert_eq!(recorder.batches(), vec![vec![1, 2, 5, 4]]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "wal")]
+#[tokio::test]
+async fn async_wal_test() {
+    use buffer_trigger::testing::Recorder;
+    use buffer_trigger::Wal;
+
+    let dir = env::temp_dir().join(format!("buffer-trigger-async-wal-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    {
+        // the consumer fails, as if the process crashed before it succeeded
+        let trigger = buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+            .accumulator(|c: &mut Vec<String>, e| c.push(e))
+            .try_consumer(|c| async { Err((c, "unavailable")) })
+            .wal(Wal::open(&dir).unwrap())
+            .build();
+        trigger.push("a".to_owned()).await;
+        trigger.push("b".to_owned()).await;
+    }
+
+    let recorder = Recorder::new();
+    let trigger = buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+        .accumulator(|c: &mut Vec<String>, e| c.push(e))
+        .async_consumer_with_info(recorder.async_consumer())
+        .wal(Wal::open(&dir).unwrap())
+        .build();
+    assert!(
+        recorder
+            .wait_for_batches_async(1, Duration::from_secs(1))
+            .await
+    );
+    recorder.assert_reasons(&[FlushReason::Replay]);
+    assert_eq!(
+        recorder.batches(),
+        vec![vec!["a".to_owned(), "b".to_owned()]]
+    );
+    // a batch flushed on drop is not replayed
+    trigger.push("c".to_owned()).await;
+    drop(trigger);
+    assert_eq!(recorder.len(), 2);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    let _trigger = buffer_trigger_async::SimpleBuilder::builder(Vec::default)
+        .accumulator(|c: &mut Vec<String>, e| c.push(e))
+        .async_consumer_with_info(recorder.async_consumer())
+        .wal(Wal::open(&dir).unwrap())
+        .build();
+    sleep(Duration::from_millis(50)).await;
+    assert_eq!(recorder.len(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+}